@@ -2,30 +2,22 @@
 #![no_main]
 #![no_std]
 
+#[cfg(not(feature = "panic_handler"))]
 use panic_halt as _;
 
-use cortex_m;
 use cortex_m_rt::entry;
-use crazyflie::hal::{self, prelude::*, stm32};
-use crazyflie::led::{LedN, Leds};
+use crazyflie::board::Board;
+use crazyflie::hal::prelude::*;
+use crazyflie::led::LedN;
 
 #[entry]
 fn main() -> ! {
-    // Get handles to device peripherals
-    let dp = stm32::Peripherals::take().unwrap();
-    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
-    // Get references to correct GPIO pins for LEDs
-    let gpioc = dp.GPIOC.split();
-    let gpiod = dp.GPIOD.split();
-    // Initialize LEDs
-    let mut leds = Leds::new(gpioc.pc0, gpioc.pc1, gpioc.pc2, gpioc.pc3, gpiod.pd2);
-    // Setup system clock for delay handling
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.sysclk(168.mhz()).freeze();
-    // Create delay abstraction
-    let mut delay = hal::delay::Delay::new(cp.SYST, clocks);
+    // Initialize all on-board peripherals
+    let board = Board::take().unwrap();
+    let mut leds = board.leds;
+    let mut delay = board.delay;
     // Loop forever blinking LEDs
-    leds.clear_all();
+    leds.clear_all().unwrap();
     loop {
         for led_idx in &[
             LedN::RedLeft,
@@ -34,9 +26,9 @@ fn main() -> ! {
             LedN::GreenRight,
             LedN::BlueLeft,
         ] {
-            leds[*led_idx].on();
+            leds[*led_idx].on().unwrap();
             delay.delay_ms(500_u32);
-            leds[*led_idx].off();
+            leds[*led_idx].off().unwrap();
         }
     }
 }
@@ -4,30 +4,19 @@
 #![no_main]
 #![no_std]
 
+#[cfg(not(feature = "panic_handler"))]
 use panic_halt as _;
 
-use cortex_m;
 use cortex_m_rt::entry;
-use crazyflie::hal::{self, prelude::*, stm32};
-use crazyflie::motor::Motors;
+use crazyflie::board::Board;
+use crazyflie::hal::prelude::*;
 
 #[entry]
 fn main() -> ! {
-    // Get handles to device peripherals
-    let dp = stm32::Peripherals::take().unwrap();
-    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
-    // Get references to correct GPIO pins for motors
-    let gpioa = dp.GPIOA.split();
-    let gpiob = dp.GPIOB.split();
-    // Setup system clock for delay handling
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.sysclk(168.mhz()).freeze();
-    // Initialize the motors
-    let mut motors = Motors::new(
-        clocks, dp.TIM2, dp.TIM4, gpioa.pa1, gpioa.pa15, gpiob.pb9, gpiob.pb11,
-    );
-    // Create delay abstraction
-    let mut delay = hal::delay::Delay::new(cp.SYST, clocks);
+    // Initialize all on-board peripherals
+    let board = Board::take().unwrap();
+    let mut motors = board.motors;
+    let mut delay = board.delay;
     // Before starting wait a bit so that users can set down the drone
     delay.delay_ms(1000_u32);
     // Make sure to enable the motors
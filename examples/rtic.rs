@@ -0,0 +1,97 @@
+//! Template for an RTIC application using the board support crate
+//!
+//! The on-board peripherals are handed to RTIC as resources so that no global
+//! `Mutex<RefCell<Option<_>>>` is needed to share them with interrupt handlers.
+#![no_main]
+#![no_std]
+
+#[cfg(not(feature = "panic_handler"))]
+use panic_halt as _;
+
+#[rtic::app(device = crazyflie::hal::pac, peripherals = true, dispatchers = [EXTI0])]
+mod app {
+    use crazyflie::board::Board;
+    use crazyflie::hal::{nb, serial};
+    use crazyflie::irq::DataReady;
+    use crazyflie::monotonic::MonoTimer;
+    use crazyflie::motor::Motors;
+    use crazyflie::status::{Status, StatusLeds};
+    use crazyflie::uart_syslink::UartComm;
+    use fugit::ExtU64;
+
+    /// Period between LED updates in milliseconds
+    const LED_PERIOD_MS: u64 = 10;
+
+    #[monotonic(binds = TIM5, default = true)]
+    type Mono = MonoTimer;
+
+    #[shared]
+    struct Shared {
+        leds: StatusLeds,
+    }
+
+    #[local]
+    struct Local {
+        syslink: UartComm,
+        motors: Motors,
+        imu_data_ready: DataReady,
+    }
+
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        // Initialize all on-board peripherals
+        let board = Board::new(cx.device, cx.core);
+        let mut leds = StatusLeds::new(board.leds).unwrap();
+        leds.set(Status::Booting);
+        let mut syslink = board.syslink;
+        syslink.enable_interrupt(serial::Event::Rxne);
+        update_leds::spawn().unwrap();
+        (
+            Shared { leds },
+            Local {
+                syslink,
+                motors: board.motors,
+                imu_data_ready: board.imu_data_ready,
+            },
+            init::Monotonics(board.mono),
+        )
+    }
+
+    #[idle]
+    fn idle(_: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
+
+    /// Receive packets from the `nRF51`
+    #[task(binds = USART6, local = [syslink], shared = [leds])]
+    fn syslink_rx(mut cx: syslink_rx::Context) {
+        loop {
+            match cx.local.syslink.receive() {
+                Ok(_packet) => cx.shared.leds.lock(|leds| leds.set(Status::RadioConnected)),
+                Err(nb::Error::WouldBlock) => break,
+                // Parse errors are handled internally, simply try again
+                Err(nb::Error::Other(_)) => {}
+            }
+        }
+    }
+
+    /// Run the control loop whenever the IMU has new data
+    #[task(binds = EXTI15_10, local = [imu_data_ready, motors])]
+    fn control_loop(cx: control_loop::Context) {
+        cx.local.imu_data_ready.clear();
+        // Read sensors, estimate state and update the motors here, for this template the motors
+        // are simply kept stopped
+        cx.local.motors.stop();
+    }
+
+    /// Periodically update the status LEDs
+    #[task(shared = [leds])]
+    fn update_leds(mut cx: update_leds::Context) {
+        cx.shared
+            .leds
+            .lock(|leds| leds.update(LED_PERIOD_MS as u32).unwrap());
+        update_leds::spawn_after(LED_PERIOD_MS.millis()).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+//! Example running the boot-time self-tests and showing the result on the LEDs
+//!
+//! The left green LED flashes five times if all tests passed, otherwise the left red LED keeps
+//! flashing.
+#![no_main]
+#![no_std]
+
+#[cfg(not(feature = "panic_handler"))]
+use panic_halt as _;
+
+use cortex_m_rt::entry;
+use crazyflie::board::Board;
+use crazyflie::hal::prelude::*;
+use crazyflie::selftest::{ChipId, EepromMagic, MotorPwm, Report, SyslinkRoundTrip};
+use crazyflie::status::StatusLeds;
+
+/// Period between LED updates in milliseconds
+const LED_PERIOD_MS: u32 = 10;
+/// Time to wait for the `nRF51` to answer in microseconds
+const SYSLINK_TIMEOUT_US: u64 = 100_000;
+
+#[entry]
+fn main() -> ! {
+    // Initialize all on-board peripherals
+    let board = Board::take().unwrap();
+    let mut delay = board.delay;
+    let mut eeprom = board.eeprom;
+    let mut sensor_i2c = board.sensor_i2c;
+    let mut syslink = board.syslink;
+    let mut motors = board.motors;
+    let mono = board.mono;
+    let mut leds = StatusLeds::new(board.leds).unwrap();
+    // Run all self-tests, the sensor tests share the I2C bus so they are run one by one
+    let mut report = Report::new();
+    report.run(&mut EepromMagic(&mut eeprom));
+    report.run(&mut ChipId::bmi088_accel(&mut sensor_i2c));
+    report.run(&mut ChipId::bmi088_gyro(&mut sensor_i2c));
+    report.run(&mut ChipId::bmp388(&mut sensor_i2c));
+    let mut syslink_test = SyslinkRoundTrip::new(&mut syslink, || mono.now(), SYSLINK_TIMEOUT_US);
+    report.run(&mut syslink_test);
+    report.run(&mut MotorPwm(&mut motors));
+    report.show(&mut leds);
+    // Loop forever showing the result
+    loop {
+        leds.update(LED_PERIOD_MS).unwrap();
+        delay.delay_ms(LED_PERIOD_MS);
+    }
+}
@@ -1,13 +1,15 @@
 //! An example to show how to read `Syslink` messages from the `nRF51`
 #![no_main]
 #![no_std]
+#[cfg(not(feature = "panic_handler"))]
 use panic_halt as _;
 
 use cortex_m;
 use cortex_m::interrupt::Mutex;
 use core::cell::RefCell;
 use cortex_m_rt::entry;
-use crazyflie::hal::{self, prelude::*, stm32, serial, nb};
+use crazyflie::board::Board;
+use crazyflie::hal::{prelude::*, stm32, serial, nb};
 use crazyflie::led::{LedN, Leds};
 use crazyflie::hal::stm32::interrupt;
 use crazyflie::uart_syslink::{UartComm};
@@ -36,7 +38,7 @@ fn USART6() {
     if let Some(ledn) = led {
         cortex_m::interrupt::free(|cs| {
             if let Some(ref mut leds) = *LEDS.borrow(cs).borrow_mut() {
-                leds[ledn].on();
+                leds[ledn].on().unwrap();
             }
         });
     }
@@ -44,24 +46,14 @@ fn USART6() {
 
 #[entry]
 fn main() -> ! {
-    // Get handles to device peripherals
-    let dp = stm32::Peripherals::take().unwrap();
-    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
-    // Get references to correct GPIO pins for LEDs
-    let gpioc = dp.GPIOC.split();
-    let gpiod = dp.GPIOD.split();
-    // Initialize LEDs
-    let mut leds = Leds::new(gpioc.pc0, gpioc.pc1, gpioc.pc2, gpioc.pc3, gpiod.pd2);
-    leds.clear_all();
+    // Initialize all on-board peripherals
+    let board = Board::take().unwrap();
+    let mut leds = board.leds;
+    let mut delay = board.delay;
+    let mut comm = board.syslink;
+    leds.clear_all().unwrap();
     // Move LEDs into a global so that we can use it in the interrupt
     cortex_m::interrupt::free(|cs| *LEDS.borrow(cs).borrow_mut() = Some(leds));
-    // Setup system clock for delay handling
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.sysclk(168.mhz()).freeze();
-    // Create delay abstraction
-    let mut delay = hal::delay::Delay::new(cp.SYST, clocks);
-    // Create Syslink interface over UART
-    let mut comm = UartComm::new(dp.USART6, gpioc.pc6, gpioc.pc7, clocks);
     // Enable interrupt for receiving before moving into global scope
     comm.enable_interrupt(serial::Event::Rxne);
     cortex_m::interrupt::free(|cs| *SYSLINK.borrow(cs).borrow_mut() = Some(comm));
@@ -73,7 +65,7 @@ fn main() -> ! {
         delay.delay_ms(300u32);
         cortex_m::interrupt::free(|cs| {
             if let Some(ref mut leds) = *LEDS.borrow(cs).borrow_mut() {
-                leds.clear_all();
+                leds.clear_all().unwrap();
             }
         });
     }
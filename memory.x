@@ -10,3 +10,16 @@ MEMORY
 /* The stack is of the full descending type. */
 /* NOTE Do NOT modify `_stack_start` unless you know what you are doing */
 _stack_start = ORIGIN(RAM) + LENGTH(RAM);
+/* To place the stack in core coupled memory use the following instead, note that DMA can not
+   access CCM RAM so buffers on the stack can then not be used for DMA transfers */
+/* _stack_start = ORIGIN(CCMRAM) + LENGTH(CCMRAM); */
+
+/* Core coupled memory, see `crazyflie::ccmram`. The section is not initialized on startup. */
+SECTIONS
+{
+  .ccmram (NOLOAD) : ALIGN(4)
+  {
+    *(.ccmram .ccmram.*);
+    . = ALIGN(4);
+  } > CCMRAM
+} INSERT AFTER .bss;
@@ -0,0 +1,119 @@
+//! Initialization of all on-board peripherals
+//!
+//! # Usage
+//! Call [`Board::take`] once at the start of the application to get access to ready to use
+//! peripherals with the correct pin assignments of the Crazyflie.
+use crate::clock;
+#[cfg(feature = "eeprom")]
+use crate::eeprom::{self, Eeprom};
+use crate::hal::delay::Delay;
+use crate::hal::pac;
+use crate::hal::prelude::*;
+use crate::hal::rcc::Clocks;
+use crate::hal::syscfg::SysCfgExt;
+use crate::irq::DataReady;
+use crate::led::Leds;
+use crate::monotonic::MonoTimer;
+use crate::motor::Motors;
+use crate::reset::ResetCause;
+use crate::sensors;
+#[cfg(feature = "uart_syslink")]
+use crate::uart_syslink::UartComm;
+
+/// All on-board peripherals of the Crazyflie
+pub struct Board {
+    /// On-board LEDs
+    pub leds: Leds,
+    /// Motors, stopped
+    pub motors: Motors,
+    /// On-board EEPROM
+    #[cfg(feature = "eeprom")]
+    pub eeprom: Eeprom,
+    /// Communication channel to the `nRF51`
+    #[cfg(feature = "uart_syslink")]
+    pub syslink: UartComm,
+    /// DMA controller used by [`DmaUartComm`](crate::uart_syslink::dma::DmaUartComm)
+    #[cfg(feature = "uart_syslink")]
+    pub dma2: pac::DMA2,
+    /// I2C bus of the on-board sensors
+    pub sensor_i2c: sensors::I2c,
+    /// Blocking delay based on `SysTick`
+    pub delay: Delay,
+    /// Microsecond timestamps based on `TIM5`
+    pub mono: MonoTimer,
+    /// Data-ready interrupt line of the IMU
+    pub imu_data_ready: DataReady,
+    /// Power controller, see [`LowPower`](crate::lowpower::LowPower)
+    pub pwr: pac::PWR,
+    /// Real-time clock, see [`LowPower`](crate::lowpower::LowPower)
+    pub rtc: pac::RTC,
+    /// Independent watchdog, see [`watchdog`](crate::watchdog)
+    pub iwdg: pac::IWDG,
+    /// Cause of the last reset
+    pub reset_cause: ResetCause,
+    /// Frozen clock configuration, see [`clock::configure`]
+    pub clocks: Clocks,
+    /// Debug control block, needed by [`CpuLoad`](crate::diagnostics::CpuLoad)
+    pub dcb: cortex_m::peripheral::DCB,
+    /// Data watchpoint and trace unit, needed by [`CpuLoad`](crate::diagnostics::CpuLoad)
+    pub dwt: cortex_m::peripheral::DWT,
+    /// Nested vectored interrupt controller
+    pub nvic: cortex_m::peripheral::NVIC,
+}
+
+impl Board {
+    /// Take the device peripherals and initialize the board
+    ///
+    /// Returns `None` if either the device or core peripherals have already been taken.
+    pub fn take() -> Option<Self> {
+        // Frameworks like RTIC take the core peripherals themselves, so they are taken first to not
+        // consume the device peripherals when that fails
+        let cp = cortex_m::Peripherals::take()?;
+        let dp = pac::Peripherals::take()?;
+        Some(Board::new(dp, cp))
+    }
+
+    /// Initialize the board from already taken peripherals
+    pub fn new(dp: pac::Peripherals, cp: cortex_m::Peripherals) -> Self {
+        let gpioa = dp.GPIOA.split();
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+        let gpiod = dp.GPIOD.split();
+        // The reset flags must be read before the RCC is constrained
+        let reset_cause = ResetCause::read_and_clear(&dp.RCC);
+        let rcc = dp.RCC.constrain();
+        let mut syscfg = dp.SYSCFG.constrain();
+        let mut exti = dp.EXTI;
+        // Unwrap safety: The clock configuration is fixed for the board, if this fails the
+        // configuration in `clock` must be corrected
+        let clocks = clock::configure(rcc.cfgr).unwrap();
+        let mut leds = Leds::new(gpioc.pc0, gpioc.pc1, gpioc.pc2, gpioc.pc3, gpiod.pd2);
+        // The on-board LEDs can not fail
+        leds.clear_all().unwrap();
+        let motors = Motors::new(
+            clocks, dp.TIM2, dp.TIM4, gpioa.pa1, gpioa.pa15, gpiob.pb9, gpiob.pb11,
+        );
+        Board {
+            leds,
+            motors,
+            #[cfg(feature = "eeprom")]
+            eeprom: eeprom::new(dp.I2C1, gpiob.pb6, gpiob.pb7, clocks),
+            #[cfg(feature = "uart_syslink")]
+            syslink: UartComm::new(dp.USART6, gpioc.pc6, gpioc.pc7, gpioa.pa4, clocks),
+            #[cfg(feature = "uart_syslink")]
+            dma2: dp.DMA2,
+            sensor_i2c: sensors::new(dp.I2C3, gpioa.pa8, gpioc.pc9, clocks),
+            delay: Delay::new(cp.SYST, clocks),
+            mono: MonoTimer::new(dp.TIM5, clocks),
+            imu_data_ready: DataReady::imu(gpioc.pc14, &mut syscfg, &mut exti),
+            pwr: dp.PWR,
+            rtc: dp.RTC,
+            iwdg: dp.IWDG,
+            reset_cause,
+            clocks,
+            dcb: cp.DCB,
+            dwt: cp.DWT,
+            nvic: cp.NVIC,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Placement of data in core coupled memory (CCM RAM)
+//!
+//! The `STM32F405` has 64 KiB of CCM RAM which is only connected to the CPU data bus. It can be
+//! accessed without wait states and without contention with DMA, which makes it ideal for hot
+//! data such as estimator state and filter buffers. DMA can *not* access CCM RAM, buffers used for
+//! DMA must therefore never be placed there.
+//!
+//! # Usage
+//! Declare statics with the [`ccmram!`](crate::ccmram!) macro. Since the `.ccmram` section (see
+//! `memory.x`) is not initialized on startup the statics are wrapped in
+//! [`MaybeUninit`](core::mem::MaybeUninit) and must be written before they are read.
+//!
+//! Code setting up DMA transfers should use [`assert_dma_capable`] to guard against buffers in
+//! CCM RAM.
+
+/// Start address of CCM RAM
+pub const START: usize = 0x1000_0000;
+/// Size of CCM RAM in bytes
+pub const SIZE: usize = 64 * 1024;
+
+/// Declare `static mut` variables placed in CCM RAM
+///
+/// The variables have type `MaybeUninit<T>` and are left uninitialized on startup.
+///
+/// ```ignore
+/// crazyflie::ccmram! {
+///     /// Filter state of the estimator
+///     static mut FILTER: [f32; 256];
+/// }
+/// ```
+#[macro_export]
+macro_rules! ccmram {
+    ($($(#[$attr:meta])* $vis:vis static mut $name:ident: $ty:ty;)+) => {
+        $(
+            $(#[$attr])*
+            #[link_section = ".ccmram"]
+            $vis static mut $name: core::mem::MaybeUninit<$ty> = core::mem::MaybeUninit::uninit();
+        )+
+    };
+}
+
+/// Check if any part of the memory range `[address, address + len)` lies in CCM RAM
+pub fn contains(address: usize, len: usize) -> bool {
+    address < START + SIZE && address.saturating_add(len) > START
+}
+
+/// Panic if the buffer lies in CCM RAM, and can therefore not be used by DMA
+pub fn assert_dma_capable(buffer: &[u8]) {
+    assert!(
+        !contains(buffer.as_ptr() as usize, buffer.len()),
+        "DMA buffer placed in CCM RAM"
+    );
+}
@@ -0,0 +1,74 @@
+//! Clock tree configuration for the Crazyflie
+//!
+//! The Crazyflie has an 8 MHz external crystal (`HSE`) which is used as the `PLL` source to get a
+//! 168 MHz system clock together with an exact 48 MHz clock for USB.
+//!
+//! # Usage
+//! Call [`configure`] with the [`CFGR`] of the constrained `RCC` instead of freezing it directly.
+use crate::hal::prelude::*;
+use crate::hal::rcc::{Clocks, CFGR};
+use crate::hal::time::Hertz;
+
+/// Frequency of the external crystal in MHz
+pub const HSE_MHZ: u32 = 8;
+/// System clock frequency in MHz
+pub const SYSCLK_MHZ: u32 = 168;
+/// `AHB` bus frequency in MHz
+pub const HCLK_MHZ: u32 = 168;
+/// `APB1` bus frequency in MHz, this is the maximum supported
+pub const PCLK1_MHZ: u32 = 42;
+/// `APB2` bus frequency in MHz, this is the maximum supported
+pub const PCLK2_MHZ: u32 = 84;
+/// USB clock frequency in MHz
+pub const USB_MHZ: u32 = 48;
+
+/// A clock in the resulting configuration did not have the expected frequency
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    /// Wrong system clock frequency
+    Sysclk(Hertz),
+    /// Wrong `AHB` bus frequency
+    Hclk(Hertz),
+    /// Wrong `APB1` bus frequency
+    Pclk1(Hertz),
+    /// Wrong `APB2` bus frequency
+    Pclk2(Hertz),
+    /// The 48 MHz clock is either not running or not exact
+    Pll48Clk(Option<Hertz>),
+}
+
+/// Configure the clock tree from the external crystal and freeze the configuration
+///
+/// The resulting [`Clocks`] are verified before they are returned.
+pub fn configure(cfgr: CFGR) -> Result<Clocks, Error> {
+    let clocks = cfgr
+        .use_hse(HSE_MHZ.mhz())
+        .sysclk(SYSCLK_MHZ.mhz())
+        .hclk(HCLK_MHZ.mhz())
+        .pclk1(PCLK1_MHZ.mhz())
+        .pclk2(PCLK2_MHZ.mhz())
+        .require_pll48clk()
+        .freeze();
+    verify(&clocks)?;
+    Ok(clocks)
+}
+
+/// Verify that the frozen [`Clocks`] match the expected board configuration
+pub fn verify(clocks: &Clocks) -> Result<(), Error> {
+    if clocks.sysclk().0 != SYSCLK_MHZ * 1_000_000 {
+        return Err(Error::Sysclk(clocks.sysclk()));
+    }
+    if clocks.hclk().0 != HCLK_MHZ * 1_000_000 {
+        return Err(Error::Hclk(clocks.hclk()));
+    }
+    if clocks.pclk1().0 != PCLK1_MHZ * 1_000_000 {
+        return Err(Error::Pclk1(clocks.pclk1()));
+    }
+    if clocks.pclk2().0 != PCLK2_MHZ * 1_000_000 {
+        return Err(Error::Pclk2(clocks.pclk2()));
+    }
+    match clocks.pll48clk() {
+        Some(freq) if freq.0 == USB_MHZ * 1_000_000 => Ok(()),
+        other => Err(Error::Pll48Clk(other)),
+    }
+}
@@ -0,0 +1,166 @@
+//! CPU load and stack usage monitoring
+//!
+//! # Usage
+//! Call [`paint_stack`] as early as possible after reset, the [high-water
+//! mark](stack_high_water_mark) of the stack can then be read at any time. To measure CPU load
+//! replace calls to `wfi` in the idle loop with [`CpuLoad::wfi`] and periodically call
+//! [`CpuLoad::update`].
+//!
+//! The measurements are exposed through [`Metrics`] which implements
+//! [`Loggable`](crate::logging::Loggable).
+use crate::logging::{LogValue, LogVariable, Loggable};
+use core::ptr;
+use cortex_m::peripheral::{DCB, DWT};
+
+/// Pattern written to unused stack memory
+const STACK_PAINT: u32 = 0xCCCC_CCCC;
+/// Number of words below the current stack pointer which are left unpainted
+const STACK_MARGIN_WORDS: usize = 16;
+
+extern "C" {
+    /// Top of the stack, set in `memory.x`
+    static _stack_start: u32;
+    /// Start of the heap, the lowest address the stack can grow to, set by `cortex-m-rt`
+    static mut __sheap: u32;
+}
+
+/// Lowest address of the stack region
+fn stack_bottom() -> *mut u32 {
+    unsafe { ptr::addr_of_mut!(__sheap) }
+}
+
+/// Highest address of the stack region
+fn stack_top() -> *mut u32 {
+    unsafe { ptr::addr_of!(_stack_start) as *mut u32 }
+}
+
+/// Total size of the stack region in bytes
+///
+/// This assumes the stack is placed directly above the statics in `RAM`, as is the default in
+/// `memory.x`.
+pub fn stack_size() -> usize {
+    stack_top() as usize - stack_bottom() as usize
+}
+
+/// Fill the unused part of the stack with a known pattern
+///
+/// # Safety
+/// Must only be called once, early after reset, and requires that nothing is placed between the
+/// end of the statics and the bottom of the stack (e.g. a heap).
+pub unsafe fn paint_stack() {
+    cortex_m::interrupt::free(|_| {
+        let end = (cortex_m::register::msp::read() as *mut u32).sub(STACK_MARGIN_WORDS);
+        let mut word = stack_bottom();
+        while word < end {
+            ptr::write_volatile(word, STACK_PAINT);
+            word = word.add(1);
+        }
+    });
+}
+
+/// Maximum number of bytes of stack used since [`paint_stack`] was called
+pub fn stack_high_water_mark() -> usize {
+    let top = stack_top();
+    let mut word = stack_bottom();
+    // Safety: Only reads words within the stack region
+    while word < top && unsafe { ptr::read_volatile(word) } == STACK_PAINT {
+        word = unsafe { word.add(1) };
+    }
+    top as usize - word as usize
+}
+
+/// Measure CPU load as the fraction of time not spent waiting for interrupts
+pub struct CpuLoad {
+    window_start: u32,
+    idle_cycles: u32,
+    load: f32,
+}
+
+impl CpuLoad {
+    /// Start the cycle counter and begin measuring
+    pub fn new(dcb: &mut DCB, dwt: &mut DWT) -> Self {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+        CpuLoad {
+            window_start: DWT::cycle_count(),
+            idle_cycles: 0,
+            load: 0.0,
+        }
+    }
+
+    /// Wait for interrupt, counting the time spent as idle
+    ///
+    /// Interrupts are disabled while waiting so that time spent in the interrupt handlers is not
+    /// counted as idle, the pending interrupt is serviced before this method returns.
+    pub fn wfi(&mut self) {
+        let idle = cortex_m::interrupt::free(|_| {
+            let start = DWT::cycle_count();
+            cortex_m::asm::wfi();
+            DWT::cycle_count().wrapping_sub(start)
+        });
+        self.idle_cycles = self.idle_cycles.wrapping_add(idle);
+    }
+
+    /// Compute the load since the last update and start a new measurement window
+    ///
+    /// The cycle counter overflows after roughly 25 seconds at 168 MHz, this must be called more
+    /// often than that. Returns the load in percent.
+    pub fn update(&mut self) -> f32 {
+        let now = DWT::cycle_count();
+        let total = now.wrapping_sub(self.window_start);
+        if total > 0 {
+            let idle = self.idle_cycles.min(total);
+            self.load = 100.0 * (1.0 - idle as f32 / total as f32);
+        }
+        self.window_start = now;
+        self.idle_cycles = 0;
+        self.load
+    }
+
+    /// Load in percent of the last completed measurement window
+    pub fn load(&self) -> f32 {
+        self.load
+    }
+}
+
+/// Snapshot of the system diagnostics
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Metrics {
+    /// CPU load in percent
+    pub cpu_load: f32,
+    /// Maximum stack usage in bytes
+    pub stack_used: u32,
+    /// Total stack size in bytes
+    pub stack_size: u32,
+}
+
+impl Metrics {
+    /// Collect the current diagnostics
+    pub fn collect(cpu: &CpuLoad) -> Self {
+        Metrics {
+            cpu_load: cpu.load(),
+            stack_used: stack_high_water_mark() as u32,
+            stack_size: stack_size() as u32,
+        }
+    }
+}
+
+impl Loggable for Metrics {
+    fn log(&self, f: &mut dyn FnMut(LogVariable)) {
+        f(LogVariable {
+            group: "sys",
+            name: "cpuLoad",
+            value: LogValue::F32(self.cpu_load),
+        });
+        f(LogVariable {
+            group: "sys",
+            name: "stackUsed",
+            value: LogValue::U32(self.stack_used),
+        });
+        f(LogVariable {
+            group: "sys",
+            name: "stackSize",
+            value: LogValue::U32(self.stack_size),
+        });
+    }
+}
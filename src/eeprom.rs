@@ -1,7 +1,19 @@
 //! Access to internal persistent storage
 //!
-//! Currently this module simply exposes the underlying [`eeprom24x::Eeprom24x`] instance by
-//! helping to build the correct setup.
+//! The on-board EEPROM is accessed through the [`eeprom24x::Eeprom24x`] driver set up by [`new`].
+//! On top of it [`ConfigBlock`] reads the configuration shared with the official firmware, and the
+//! [`reset`](crate::reset) module keeps a history of reset causes.
+//!
+//! # Memory layout
+//! The 8 KiB EEPROM is shared with the official firmware and divided into the following regions:
+//!
+//! - `0x0000..0x0015` - Config block, see [`CONFIG_BLOCK_ADDRESS`]
+//! - `0x0015..0x03E0` - Unused
+//! - `0x03E0..0x0400` - History of reset causes, see [`RESET_HISTORY_ADDRESS`]
+//! - `0x0400..0x2000` - Key-value storage of the official firmware, see [`KV_STORAGE`]
+//!
+//! The key-value storage must not be written by this crate, otherwise the parameters stored by the
+//! official firmware are corrupted.
 use crate::hal::gpio::{
     gpiob::{PB6, PB7},
     AlternateOD, Floating, Input, AF4,
@@ -16,6 +28,17 @@ use eeprom24x::{addr_size::TwoBytes, page_size::B32, Eeprom24x, SlaveAddr};
 pub type I2c = i2c::I2c<I2C1, (PB6<AlternateOD<AF4>>, PB7<AlternateOD<AF4>>)>;
 /// Full type of on-board EEPROM
 pub type Eeprom = Eeprom24x<I2c, B32, TwoBytes>;
+/// Errors when accessing the on-board EEPROM
+pub type Error = eeprom24x::Error<i2c::Error>;
+
+/// Address of the Crazyflie config block
+pub const CONFIG_BLOCK_ADDRESS: u32 = 0x0000;
+/// Address of the reset history, the last page before [`KV_STORAGE`]
+pub const RESET_HISTORY_ADDRESS: u32 = 0x03E0;
+/// Key-value storage partition of the official firmware, not used by this crate
+pub const KV_STORAGE: core::ops::Range<u32> = 0x0400..0x2000;
+/// Size of a single EEPROM page in bytes
+pub const PAGE_SIZE: usize = 32;
 
 /// I2C speed in kHz
 const I2C_SPEED_KHZ: u32 = 400;
@@ -32,3 +55,106 @@ pub fn new(
     let i2c: I2c = i2c::I2c::new(i2c1, (scl, sda), I2C_SPEED_KHZ.khz(), clocks);
     Eeprom24x::new_24x64(i2c, SlaveAddr::default())
 }
+
+/// Magic number at the start of a valid config block
+pub const CONFIG_BLOCK_MAGIC: u32 = 0x43427830;
+/// Size of a version 0 config block, without radio address
+const CONFIG_BLOCK_V0_SIZE: usize = 16;
+/// Size of a version 1 config block
+const CONFIG_BLOCK_V1_SIZE: usize = 21;
+/// Default radio channel of the official firmware
+const DEFAULT_RADIO_CHANNEL: u8 = 80;
+/// Default radio data rate of the official firmware (2 Mbit/s)
+const DEFAULT_RADIO_SPEED: u8 = 2;
+
+/// Potential errors when reading the config block
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Problem reading from the EEPROM
+    Eeprom(Error),
+    /// The config block does not start with [`CONFIG_BLOCK_MAGIC`]
+    Magic(u32),
+    /// Unsupported config block version
+    Version(u8),
+    /// The checksum of the config block does not match
+    Checksum,
+}
+
+/// Configuration of the Crazyflie stored at [`CONFIG_BLOCK_ADDRESS`]
+///
+/// The layout is shared with the [official
+/// firmware](https://github.com/bitcraze/crazyflie-firmware/blob/master/src/hal/src/configblockeeprom.c).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConfigBlock {
+    /// Radio channel
+    pub radio_channel: u8,
+    /// Radio data rate, `0` - 250 kbit/s, `1` - 1 Mbit/s, `2` - 2 Mbit/s
+    pub radio_speed: u8,
+    /// Pitch trim
+    pub calib_pitch: f32,
+    /// Roll trim
+    pub calib_roll: f32,
+    /// 40-bit radio address
+    pub radio_address: u64,
+}
+
+impl ConfigBlock {
+    /// Read the config block from EEPROM
+    ///
+    /// Version 0 config blocks do not contain a radio address, the
+    /// [default](crate::uid::default_radio_address) is used in that case.
+    pub fn read(eeprom: &mut Eeprom) -> Result<Self, ConfigError> {
+        let mut data = [0u8; CONFIG_BLOCK_V1_SIZE];
+        eeprom
+            .read_data(CONFIG_BLOCK_ADDRESS, &mut data)
+            .map_err(ConfigError::Eeprom)?;
+        let magic = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        if magic != CONFIG_BLOCK_MAGIC {
+            return Err(ConfigError::Magic(magic));
+        }
+        let size = match data[4] {
+            0 => CONFIG_BLOCK_V0_SIZE,
+            1 => CONFIG_BLOCK_V1_SIZE,
+            version => return Err(ConfigError::Version(version)),
+        };
+        let checksum = data[..size - 1]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if checksum != data[size - 1] {
+            return Err(ConfigError::Checksum);
+        }
+        let f32_at =
+            |i: usize| f32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let radio_address = if size == CONFIG_BLOCK_V1_SIZE {
+            ((data[15] as u64) << 32)
+                | u32::from_le_bytes([data[16], data[17], data[18], data[19]]) as u64
+        } else {
+            crate::uid::default_radio_address()
+        };
+        Ok(ConfigBlock {
+            radio_channel: data[5],
+            radio_speed: data[6],
+            calib_pitch: f32_at(7),
+            calib_roll: f32_at(11),
+            radio_address,
+        })
+    }
+
+    /// Read the config block, falling back to the [default](Default) if it is missing or invalid
+    pub fn read_or_default(eeprom: &mut Eeprom) -> Self {
+        ConfigBlock::read(eeprom).unwrap_or_default()
+    }
+}
+
+impl Default for ConfigBlock {
+    /// Default configuration with a radio address derived from the unique device ID
+    fn default() -> Self {
+        ConfigBlock {
+            radio_channel: DEFAULT_RADIO_CHANNEL,
+            radio_speed: DEFAULT_RADIO_SPEED,
+            calib_pitch: 0.0,
+            calib_roll: 0.0,
+            radio_address: crate::uid::default_radio_address(),
+        }
+    }
+}
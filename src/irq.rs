@@ -0,0 +1,107 @@
+//! Interrupt bindings of the on-board peripherals
+//!
+//! Use these constants to bind interrupt handlers, e.g. as `binds` of RTIC tasks, instead of
+//! looking up which interrupt each peripheral is connected to.
+use crate::hal::gpio::{gpioc::PC14, Edge, ExtiPin, Floating, Input};
+use crate::hal::pac::EXTI;
+use crate::hal::syscfg::SysCfg;
+#[cfg(feature = "async")]
+use crate::waker::WakerSlot;
+#[cfg(feature = "async")]
+use core::{future::poll_fn, task::Poll};
+
+pub use crate::hal::pac::Interrupt;
+
+/// Interrupt of the UART connection to the `nRF51`
+pub const SYSLINK: Interrupt = Interrupt::USART6;
+/// Interrupt of the DMA stream receiving from the `nRF51`, see
+/// [`DmaUartComm`](crate::uart_syslink::dma::DmaUartComm)
+pub const SYSLINK_DMA_RX: Interrupt = Interrupt::DMA2_STREAM1;
+/// Interrupt of the DMA stream sending to the `nRF51`, see
+/// [`DmaUartComm`](crate::uart_syslink::dma::DmaUartComm)
+pub const SYSLINK_DMA_TX: Interrupt = Interrupt::DMA2_STREAM6;
+/// Interrupt of the `nRF51` flow control line
+pub const SYSLINK_FLOW_CONTROL: Interrupt = Interrupt::EXTI4;
+/// Interrupt of the monotonic timer
+pub const MONOTONIC: Interrupt = Interrupt::TIM5;
+/// Interrupt of the IMU data-ready line
+pub const IMU_DATA_READY: Interrupt = Interrupt::EXTI15_10;
+
+/// Pin connected to the data-ready interrupt of the IMU gyroscope
+pub type ImuIntPin = PC14<Input<Floating>>;
+
+/// EXTI line of the IMU data-ready pin
+#[cfg(feature = "async")]
+const IMU_EXTI_LINE: u32 = 14;
+
+/// Task waiting for IMU data
+#[cfg(feature = "async")]
+static IMU_WAKER: WakerSlot = WakerSlot::new();
+
+/// Handle the [`IMU_DATA_READY`] interrupt, waking the task waiting in [`DataReady::wait`]
+///
+/// The interrupt line is masked until the woken task needs to wait again.
+#[cfg(feature = "async")]
+pub fn on_imu_interrupt() {
+    set_line_mask(IMU_EXTI_LINE, false);
+    IMU_WAKER.wake();
+}
+
+/// Mask or unmask an `EXTI` interrupt line
+#[cfg(feature = "async")]
+fn set_line_mask(line: u32, enabled: bool) {
+    cortex_m::interrupt::free(|_| {
+        // Safety: Only the bit of the given line is changed inside a critical section
+        let exti = unsafe { &*EXTI::ptr() };
+        exti.imr.modify(|r, w| unsafe {
+            if enabled {
+                w.bits(r.bits() | (1 << line))
+            } else {
+                w.bits(r.bits() & !(1 << line))
+            }
+        });
+    });
+}
+
+/// Data-ready interrupt line from a sensor
+pub struct DataReady {
+    pin: ImuIntPin,
+}
+
+impl DataReady {
+    /// Configure the IMU data-ready line to trigger [`IMU_DATA_READY`] on rising edges
+    pub fn imu(mut pin: ImuIntPin, syscfg: &mut SysCfg, exti: &mut EXTI) -> Self {
+        pin.make_interrupt_source(syscfg);
+        pin.trigger_on_edge(exti, Edge::RISING);
+        pin.enable_interrupt(exti);
+        DataReady { pin }
+    }
+
+    /// Check if the data-ready interrupt is pending
+    pub fn is_pending(&self) -> bool {
+        self.pin.check_interrupt()
+    }
+
+    /// Clear the pending interrupt, must be called from the interrupt handler
+    pub fn clear(&mut self) {
+        self.pin.clear_interrupt_pending_bit();
+    }
+
+    /// Wait until new data is ready
+    ///
+    /// Requires [`on_imu_interrupt`] to be called from the [`IMU_DATA_READY`] interrupt handler.
+    #[cfg(feature = "async")]
+    pub async fn wait(&mut self) {
+        poll_fn(|cx| {
+            if self.is_pending() {
+                self.clear();
+                Poll::Ready(())
+            } else {
+                IMU_WAKER.register(cx.waker());
+                set_line_mask(IMU_EXTI_LINE, true);
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
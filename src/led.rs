@@ -3,12 +3,18 @@
 //! # Usage
 //! Instantiate the [`Leds`](Leds::new) structure and use [`LedN`] to index this structure to
 //! access the individual LEDs. The interface to each LED is controlled through [`Led`].
+//!
+//! Both [`Led`] and [`Leds`] are generic over any [`OutputPin`] so that the same code can drive
+//! the on-board LEDs, LEDs on expansion decks or mocked pins when testing on the host. The
+//! on-board LEDs use [`OnboardPin`] which is the default pin type of [`Leds`].
 
 use crate::hal::gpio::gpioc::{PC, PC0, PC1, PC2, PC3};
 use crate::hal::gpio::gpiod::{PD, PD2};
 use crate::hal::gpio::{Floating, Input, Output, PushPull, Speed};
 use crate::hal::prelude::*;
+use core::convert::Infallible;
 use core::ops::{Index, IndexMut};
+use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin};
 
 /// Blue LED on the left
 pub type BlueLedLeft = PD2<Output<PushPull>>;
@@ -21,52 +27,134 @@ pub type GreenLedRight = PC2<Output<PushPull>>;
 /// Red LED on the right
 pub type RedLedRight = PC3<Output<PushPull>>;
 
-/// Abstraction over one of the LEDs on the Crazyflie
-pub enum Led {
+/// Electrical polarity of a LED
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    /// The LED is lit when the pin is driven high
+    ActiveHigh,
+    /// The LED is lit when the pin is driven low
+    ActiveLow,
+}
+
+/// Output pin of one of the on-board LEDs
+///
+/// The on-board LEDs are spread over `GPIOC` and `GPIOD`, this type erases the port so that all
+/// on-board LEDs can be stored in the same [`Leds`] container.
+pub enum OnboardPin {
     /// LED on GPIOC
-    ///
-    /// These LEDs have reverse polarity compared to [`Led::LedD`]
-    LedC(PC<Output<PushPull>>),
+    C(PC<Output<PushPull>>),
     /// LED on GPIOD
-    LedD(PD<Output<PushPull>>),
+    D(PD<Output<PushPull>>),
 }
 
-impl Led {
-    /// Turn LED off
-    pub fn off(&mut self) {
+impl OutputPin for OnboardPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
         match self {
-            // The following unwraps can never fail since the error is `Infallible`
-            Led::LedC(pin) => pin.set_high().unwrap(),
-            Led::LedD(pin) => pin.set_low().unwrap(),
+            OnboardPin::C(pin) => pin.set_low(),
+            OnboardPin::D(pin) => pin.set_low(),
         }
     }
 
-    /// Turn the LED on
-    pub fn on(&mut self) {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        match self {
+            OnboardPin::C(pin) => pin.set_high(),
+            OnboardPin::D(pin) => pin.set_high(),
+        }
+    }
+}
+
+impl StatefulOutputPin for OnboardPin {
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
         match self {
-            // The following unwraps can never fail since the error is `Infallible`
-            Led::LedC(pin) => pin.set_low().unwrap(),
-            Led::LedD(pin) => pin.set_high().unwrap(),
+            OnboardPin::C(pin) => pin.is_set_high(),
+            OnboardPin::D(pin) => pin.is_set_high(),
         }
     }
 
-    /// Check if the LED is turned on
-    pub fn is_on(&self) -> bool {
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
         match self {
-            // The following unwraps can never fail since the error is `Infallible`
-            Led::LedC(pin) => pin.is_set_low().unwrap(),
-            Led::LedD(pin) => pin.is_set_high().unwrap(),
+            OnboardPin::C(pin) => pin.is_set_low(),
+            OnboardPin::D(pin) => pin.is_set_low(),
+        }
+    }
+}
+
+/// Abstraction over a single LED connected to an [`OutputPin`]
+pub struct Led<P> {
+    pin: P,
+    polarity: Polarity,
+}
+
+impl<P: OutputPin> Led<P> {
+    /// Create a new LED from an output pin with the given polarity
+    pub fn new(pin: P, polarity: Polarity) -> Self {
+        Led { pin, polarity }
+    }
+
+    /// Turn LED off
+    pub fn off(&mut self) -> Result<(), P::Error> {
+        match self.polarity {
+            Polarity::ActiveHigh => self.pin.set_low(),
+            Polarity::ActiveLow => self.pin.set_high(),
+        }
+    }
+
+    /// Turn the LED on
+    pub fn on(&mut self) -> Result<(), P::Error> {
+        match self.polarity {
+            Polarity::ActiveHigh => self.pin.set_high(),
+            Polarity::ActiveLow => self.pin.set_low(),
+        }
+    }
+
+    /// Turn the LED on or off
+    pub fn set(&mut self, on: bool) -> Result<(), P::Error> {
+        if on {
+            self.on()
+        } else {
+            self.off()
+        }
+    }
+
+    /// Get the polarity of the LED
+    pub fn polarity(&self) -> Polarity {
+        self.polarity
+    }
+
+    /// Release the underlying pin
+    pub fn free(self) -> P {
+        self.pin
+    }
+}
+
+impl<P: StatefulOutputPin> Led<P> {
+    /// Check if the LED is turned on
+    pub fn is_on(&self) -> Result<bool, P::Error> {
+        match self.polarity {
+            Polarity::ActiveHigh => self.pin.is_set_high(),
+            Polarity::ActiveLow => self.pin.is_set_low(),
         }
     }
 
     /// Check if the LED is turned off
-    pub fn is_off(&self) -> bool {
-        !self.is_on()
+    pub fn is_off(&self) -> Result<bool, P::Error> {
+        self.is_on().map(|on| !on)
+    }
+
+    /// Toggle the LED
+    pub fn toggle(&mut self) -> Result<(), P::Error> {
+        if self.is_on()? {
+            self.off()
+        } else {
+            self.on()
+        }
     }
 }
 
 /// A specific LED, use this to index [`Leds`] to get desired LED
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum LedN {
     /// Red LED on the left
     RedLeft,
@@ -81,12 +169,14 @@ pub enum LedN {
 }
 
 /// Container for LEDs on the Crazyflie
-pub struct Leds {
-    leds: [Led; 5],
+pub struct Leds<P = OnboardPin> {
+    leds: [Led<P>; 5],
 }
 
-impl Leds {
+impl Leds<OnboardPin> {
     /// Initialize the LEDs on the Crazyflie
+    ///
+    /// The LEDs on `GPIOC` are active-low while the blue LED on `GPIOD` is active-high.
     pub fn new(
         pc0: PC0<Input<Floating>>,
         pc1: PC1<Input<Floating>>,
@@ -94,57 +184,147 @@ impl Leds {
         pc3: PC3<Input<Floating>>,
         pd2: PD2<Input<Floating>>,
     ) -> Self {
-        let red_left = Led::LedC(
-            pc0.into_push_pull_output()
-                .set_speed(Speed::Medium)
-                .downgrade(),
+        let red_left = Led::new(
+            OnboardPin::C(
+                pc0.into_push_pull_output()
+                    .set_speed(Speed::Medium)
+                    .downgrade(),
+            ),
+            Polarity::ActiveLow,
         );
-        let green_left = Led::LedC(
-            pc1.into_push_pull_output()
-                .set_speed(Speed::Medium)
-                .downgrade(),
+        let green_left = Led::new(
+            OnboardPin::C(
+                pc1.into_push_pull_output()
+                    .set_speed(Speed::Medium)
+                    .downgrade(),
+            ),
+            Polarity::ActiveLow,
         );
-        let blue_left = Led::LedD(
-            pd2.into_push_pull_output()
-                .set_speed(Speed::Medium)
-                .downgrade(),
+        let blue_left = Led::new(
+            OnboardPin::D(
+                pd2.into_push_pull_output()
+                    .set_speed(Speed::Medium)
+                    .downgrade(),
+            ),
+            Polarity::ActiveHigh,
         );
-        let green_right = Led::LedC(
-            pc2.into_push_pull_output()
-                .set_speed(Speed::Medium)
-                .downgrade(),
+        let green_right = Led::new(
+            OnboardPin::C(
+                pc2.into_push_pull_output()
+                    .set_speed(Speed::Medium)
+                    .downgrade(),
+            ),
+            Polarity::ActiveLow,
         );
-        let red_right = Led::LedC(
-            pc3.into_push_pull_output()
-                .set_speed(Speed::Medium)
-                .downgrade(),
+        let red_right = Led::new(
+            OnboardPin::C(
+                pc3.into_push_pull_output()
+                    .set_speed(Speed::Medium)
+                    .downgrade(),
+            ),
+            Polarity::ActiveLow,
         );
-        Leds {
-            leds: [red_left, green_left, blue_left, red_right, green_right],
-        }
+        Leds::from_leds([red_left, green_left, blue_left, red_right, green_right])
+    }
+}
+
+impl<P: OutputPin> Leds<P> {
+    /// Create a LED container from individual LEDs
+    ///
+    /// The LEDs must be given in the same order as the variants of [`LedN`].
+    pub fn from_leds(leds: [Led<P>; 5]) -> Self {
+        Leds { leds }
     }
 
     /// Turn off all LEDs
-    pub fn clear_all(&mut self) {
-        self.leds.iter_mut().for_each(|l| l.off());
+    pub fn clear_all(&mut self) -> Result<(), P::Error> {
+        for led in self.leds.iter_mut() {
+            led.off()?;
+        }
+        Ok(())
     }
 
     /// Turn all LEDs on
-    pub fn set_all(&mut self) {
-        self.leds.iter_mut().for_each(|l| l.on());
+    pub fn set_all(&mut self) -> Result<(), P::Error> {
+        for led in self.leds.iter_mut() {
+            led.on()?;
+        }
+        Ok(())
+    }
+
+    /// Release the individual LEDs
+    pub fn free(self) -> [Led<P>; 5] {
+        self.leds
     }
 }
 
-impl Index<LedN> for Leds {
-    type Output = Led;
+impl<P> Index<LedN> for Leds<P> {
+    type Output = Led<P>;
 
     fn index(&self, led: LedN) -> &Self::Output {
         &self.leds[led as usize]
     }
 }
 
-impl IndexMut<LedN> for Leds {
+impl<P> IndexMut<LedN> for Leds<P> {
     fn index_mut(&mut self, led: LedN) -> &mut Self::Output {
         &mut self.leds[led as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::pin::{Mock, State, Transaction};
+
+    /// Turn the LED on and off, first directly and then through `set`
+    fn blink(led: &mut Led<Mock>) {
+        led.on().unwrap();
+        led.off().unwrap();
+        led.set(true).unwrap();
+        led.set(false).unwrap();
+    }
+
+    #[test]
+    fn active_high_drives_pin_high_when_on() {
+        let expectations = [
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+        ];
+        let mut led = Led::new(Mock::new(&expectations), Polarity::ActiveHigh);
+        blink(&mut led);
+        led.free().done();
+    }
+
+    #[test]
+    fn active_low_drives_pin_low_when_on() {
+        let expectations = [
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+        ];
+        let mut led = Led::new(Mock::new(&expectations), Polarity::ActiveLow);
+        blink(&mut led);
+        led.free().done();
+    }
+
+    #[test]
+    fn clear_all_respects_polarity() {
+        let high = [Transaction::set(State::High)];
+        let low = [Transaction::set(State::Low)];
+        let mut leds = Leds::from_leds([
+            Led::new(Mock::new(&high), Polarity::ActiveLow),
+            Led::new(Mock::new(&high), Polarity::ActiveLow),
+            Led::new(Mock::new(&low), Polarity::ActiveHigh),
+            Led::new(Mock::new(&high), Polarity::ActiveLow),
+            Led::new(Mock::new(&high), Polarity::ActiveLow),
+        ]);
+        leds.clear_all().unwrap();
+        for led in leds.free().iter_mut() {
+            led.pin.done();
+        }
+    }
+}
@@ -1,12 +1,34 @@
 //! Board support crate for the main processor (`STM32F405`) of the [Crazyflie
 //! 2.1](https://www.bitcraze.io)
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 pub use stm32f4xx_hal as hal;
 
+pub mod board;
+pub mod ccmram;
+pub mod clock;
+pub mod diagnostics;
 #[cfg(feature = "eeprom")]
 pub mod eeprom;
+pub mod irq;
 pub mod led;
+#[cfg(feature = "uart_syslink")]
+pub mod link;
+pub mod logging;
+pub mod lowpower;
+pub mod monotonic;
 pub mod motor;
+#[cfg(feature = "panic_handler")]
+pub mod panic;
+#[cfg(feature = "uart_syslink")]
+pub mod power;
+pub mod reset;
+pub mod selftest;
+pub mod sensors;
+pub mod status;
 #[cfg(feature = "uart_syslink")]
 pub mod uart_syslink;
+pub mod uid;
+#[cfg(feature = "async")]
+mod waker;
+pub mod watchdog;
@@ -0,0 +1,333 @@
+//! Radio link quality estimation
+//!
+//! [`LinkQuality`] consumes the syslink radio messages and keeps smoothed estimates of the signal
+//! strength, the rate of packets received over the radio and the rate of retries. From these a
+//! single [`LinkState`] is derived, with [`LinkEvent`]s raised when it changes, so that the motor
+//! cut-off and the status LEDs agree on whether the link is healthy.
+//!
+//! The signal strength is reported by the `nRF51` with every acknowledgement it sends. Any radio
+//! packet or signal strength report counts as the link being alive, the packet rate only counts
+//! packets carrying data.
+//!
+//! The `nRF51` does not report its own radio-level retries, so the retry rate is based on the
+//! acknowledgements the application expects. Received [`Radio::P2pAck`] packets are recorded
+//! automatically, the application records other acknowledgements, and every expected
+//! acknowledgement which never arrived, with [`LinkQuality::record_ack`]. Without such records
+//! the retry rate stays at zero and does not affect the link state.
+//!
+//! # Usage
+//! Pass [`LinkQuality`] to [`dispatch`](crate::uart_syslink::dispatch::dispatch) with every
+//! received packet and call [`LinkQuality::update`] periodically, e.g. every 10 ms, with the
+//! current time from [`MonoTimer`](crate::monotonic::MonoTimer).
+use crate::logging::{LogValue, LogVariable, Loggable};
+use crate::uart_syslink::dispatch::Handler;
+use crate::uart_syslink::message::Radio;
+
+/// Thresholds and smoothing of the link quality estimate
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Weight of a new sample in the moving averages, between `0` and `1`
+    pub smoothing: f32,
+    /// Signal strength in `-dBm` above which the link is degraded
+    pub max_rssi: f32,
+    /// Packets per second below which the link is degraded
+    pub min_packet_rate: f32,
+    /// Fraction of retries above which the link is degraded
+    pub max_retry_rate: f32,
+    /// Time in microseconds without any radio traffic after which the link is lost
+    pub lost_timeout_us: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            smoothing: 0.1,
+            max_rssi: 85.0,
+            min_packet_rate: 10.0,
+            max_retry_rate: 0.3,
+            lost_timeout_us: 500_000,
+        }
+    }
+}
+
+/// Health of the radio link
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    /// Packets are received with good quality
+    Healthy,
+    /// Packets are received, but at least one of the metrics crossed its threshold
+    Degraded,
+    /// No packets have been received within the timeout, or none at all
+    Lost,
+}
+
+/// Change of the [`LinkState`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// The link became healthy
+    Healthy,
+    /// The link became degraded
+    Degraded,
+    /// The link was lost
+    Lost,
+}
+
+/// Smoothed link quality metrics and state
+pub struct LinkQuality {
+    config: Config,
+    state: LinkState,
+    rssi: Option<f32>,
+    packet_rate: f32,
+    retry_rate: f32,
+    packets: u32,
+    heard: bool,
+    last_update_us: Option<u64>,
+    last_packet_us: Option<u64>,
+}
+
+impl LinkQuality {
+    /// Start estimating with the link lost until packets are received
+    pub fn new(config: Config) -> Self {
+        LinkQuality {
+            config,
+            state: LinkState::Lost,
+            rssi: None,
+            packet_rate: 0.0,
+            retry_rate: 0.0,
+            packets: 0,
+            heard: false,
+            last_update_us: None,
+            last_packet_us: None,
+        }
+    }
+
+    /// Record the outcome of a packet which should have been acknowledged
+    pub fn record_ack(&mut self, acked: bool) {
+        let sample = if acked { 0.0 } else { 1.0 };
+        self.retry_rate = self.average(self.retry_rate, sample);
+    }
+
+    /// Update the packet rate and link state, returning an event if the state changed
+    ///
+    /// `now_us` is the current time in microseconds.
+    pub fn update(&mut self, now_us: u64) -> Option<LinkEvent> {
+        if self.heard {
+            self.last_packet_us = Some(now_us);
+        }
+        if let Some(last) = self.last_update_us {
+            let elapsed = now_us.saturating_sub(last);
+            if elapsed > 0 {
+                let rate = self.packets as f32 * 1_000_000.0 / elapsed as f32;
+                self.packet_rate = self.average(self.packet_rate, rate);
+            }
+        }
+        self.packets = 0;
+        self.heard = false;
+        self.last_update_us = Some(now_us);
+        let state = self.evaluate(now_us);
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        Some(match state {
+            LinkState::Healthy => LinkEvent::Healthy,
+            LinkState::Degraded => LinkEvent::Degraded,
+            LinkState::Lost => LinkEvent::Lost,
+        })
+    }
+
+    /// Current state of the link
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    /// Check if the link is healthy
+    pub fn is_healthy(&self) -> bool {
+        self.state == LinkState::Healthy
+    }
+
+    /// Smoothed signal strength in `-dBm`
+    pub fn rssi(&self) -> Option<f32> {
+        self.rssi
+    }
+
+    /// Smoothed number of packets received per second
+    pub fn packet_rate(&self) -> f32 {
+        self.packet_rate
+    }
+
+    /// Smoothed fraction of expected acknowledgements which were not received
+    pub fn retry_rate(&self) -> f32 {
+        self.retry_rate
+    }
+
+    /// Derive the link state from the current metrics
+    fn evaluate(&self, now_us: u64) -> LinkState {
+        match self.last_packet_us {
+            Some(last) if now_us.saturating_sub(last) <= self.config.lost_timeout_us => {}
+            _ => return LinkState::Lost,
+        }
+        let weak_signal = self.rssi.map_or(false, |rssi| rssi > self.config.max_rssi);
+        if weak_signal
+            || self.packet_rate < self.config.min_packet_rate
+            || self.retry_rate > self.config.max_retry_rate
+        {
+            LinkState::Degraded
+        } else {
+            LinkState::Healthy
+        }
+    }
+
+    /// Exponentially weighted moving average
+    fn average(&self, average: f32, sample: f32) -> f32 {
+        average + self.config.smoothing * (sample - average)
+    }
+}
+
+impl Default for LinkQuality {
+    fn default() -> Self {
+        LinkQuality::new(Config::default())
+    }
+}
+
+impl Handler for LinkQuality {
+    fn radio(&mut self, message: Radio<'_>) {
+        match message {
+            Radio::Raw(_) | Radio::RawBroadcast(_) => {
+                self.heard = true;
+                self.packets = self.packets.wrapping_add(1);
+            }
+            Radio::Rssi(rssi) => {
+                self.heard = true;
+                let rssi = rssi as f32;
+                self.rssi = Some(self.rssi.map_or(rssi, |avg| self.average(avg, rssi)));
+            }
+            Radio::P2pAck(_) => {
+                self.heard = true;
+                self.record_ack(true);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Loggable for LinkQuality {
+    fn log(&self, f: &mut dyn FnMut(LogVariable)) {
+        if let Some(rssi) = self.rssi {
+            f(LogVariable {
+                group: "radio",
+                name: "rssi",
+                value: LogValue::F32(rssi),
+            });
+        }
+        f(LogVariable {
+            group: "radio",
+            name: "packetRate",
+            value: LogValue::F32(self.packet_rate),
+        });
+        f(LogVariable {
+            group: "radio",
+            name: "retryRate",
+            value: LogValue::F32(self.retry_rate),
+        });
+        f(LogVariable {
+            group: "radio",
+            name: "isHealthy",
+            value: LogValue::U8(self.is_healthy() as u8),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interval between updates in microseconds
+    const PERIOD_US: u64 = 10_000;
+
+    /// Run `steps` update periods starting at `*now_us`, receiving `packets` radio packets and
+    /// optionally a signal strength each period, and return the raised events
+    fn run(
+        link: &mut LinkQuality,
+        now_us: &mut u64,
+        steps: usize,
+        packets: usize,
+        rssi: Option<u8>,
+    ) -> std::vec::Vec<LinkEvent> {
+        let mut events = std::vec::Vec::new();
+        for _ in 0..steps {
+            for _ in 0..packets {
+                link.radio(Radio::Raw(&[]));
+            }
+            if let Some(rssi) = rssi {
+                link.radio(Radio::Rssi(rssi));
+            }
+            events.extend(link.update(*now_us));
+            *now_us += PERIOD_US;
+        }
+        events
+    }
+
+    /// A link which has become healthy
+    fn healthy(now_us: &mut u64) -> LinkQuality {
+        let mut link = LinkQuality::default();
+        let events = run(&mut link, now_us, 50, 2, Some(40));
+        assert_eq!(events.last(), Some(&LinkEvent::Healthy));
+        assert!(link.is_healthy());
+        link
+    }
+
+    #[test]
+    fn starts_lost() {
+        let mut link = LinkQuality::default();
+        assert_eq!(link.state(), LinkState::Lost);
+        assert_eq!(link.update(0), None);
+    }
+
+    #[test]
+    fn degraded_on_weak_signal() {
+        let mut now_us = 0;
+        let mut link = healthy(&mut now_us);
+        let events = run(&mut link, &mut now_us, 50, 2, Some(95));
+        assert_eq!(events, vec![LinkEvent::Degraded]);
+        let events = run(&mut link, &mut now_us, 50, 2, Some(40));
+        assert_eq!(events, vec![LinkEvent::Healthy]);
+    }
+
+    #[test]
+    fn degraded_on_missing_acks() {
+        let mut now_us = 0;
+        let mut link = healthy(&mut now_us);
+        for _ in 0..5 {
+            link.record_ack(false);
+        }
+        assert!(link.retry_rate() > 0.3);
+        let events = run(&mut link, &mut now_us, 1, 2, Some(40));
+        assert_eq!(events, vec![LinkEvent::Degraded]);
+        for _ in 0..20 {
+            link.radio(Radio::P2pAck(&[]));
+        }
+        let events = run(&mut link, &mut now_us, 1, 2, Some(40));
+        assert_eq!(events, vec![LinkEvent::Healthy]);
+    }
+
+    #[test]
+    fn signal_strength_keeps_link_alive() {
+        let mut now_us = 0;
+        let mut link = healthy(&mut now_us);
+        let events = run(&mut link, &mut now_us, 100, 0, Some(40));
+        assert_eq!(events, vec![LinkEvent::Degraded]);
+        assert_eq!(link.state(), LinkState::Degraded);
+    }
+
+    #[test]
+    fn degraded_then_lost_without_packets() {
+        let mut now_us = 0;
+        let mut link = healthy(&mut now_us);
+        let events = run(&mut link, &mut now_us, 40, 0, None);
+        assert_eq!(events, vec![LinkEvent::Degraded]);
+        let events = run(&mut link, &mut now_us, 20, 0, None);
+        assert_eq!(events, vec![LinkEvent::Lost]);
+        assert_eq!(link.state(), LinkState::Lost);
+    }
+}
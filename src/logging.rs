@@ -0,0 +1,46 @@
+//! Logging of named variables
+//!
+//! Similar to the log subsystem of the [official
+//! firmware](https://www.bitcraze.io/documentation/repository/crazyflie-firmware/master/userguides/logparam/)
+//! variables are identified by a group and a name. Subsystems implement [`Loggable`] to expose
+//! their variables, the application decides how they are transported, e.g. over the radio.
+use core::fmt;
+
+/// Value of a logged variable
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LogValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+}
+
+impl fmt::Display for LogValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogValue::U8(v) => write!(f, "{}", v),
+            LogValue::U16(v) => write!(f, "{}", v),
+            LogValue::U32(v) => write!(f, "{}", v),
+            LogValue::I32(v) => write!(f, "{}", v),
+            LogValue::F32(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// A single logged variable
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LogVariable {
+    /// Group of the variable, e.g. `sys`
+    pub group: &'static str,
+    /// Name of the variable within the group
+    pub name: &'static str,
+    /// Current value
+    pub value: LogValue,
+}
+
+/// A subsystem which exposes variables for logging
+pub trait Loggable {
+    /// Call `f` once for every variable with its current value
+    fn log(&self, f: &mut dyn FnMut(LogVariable));
+}
@@ -0,0 +1,211 @@
+//! Low-power `STOP` mode with wake-up from syslink or a timer
+//!
+//! In `STOP` mode all clocks except the low-speed oscillators are stopped while `SRAM` and
+//! register contents are kept. The Crazyflie is woken by activity on the `USART6` receive line
+//! (`PC7`), i.e. when the `nRF51` starts sending syslink packets, or by the `RTC` wake-up timer
+//! clocked from the internal low-speed oscillator (`LSI`).
+//!
+//! # Usage
+//! Create [`LowPower`] from the `PWR` and `RTC` peripherals kept on the
+//! [`Board`](crate::board::Board) and call [`LowPower::stop`] when the Crazyflie sits idle on the
+//! ground. Nothing is configured until [`LowPower::new`] is called, applications which never enter
+//! `STOP` mode are not affected. The motors are stopped and disabled before entering `STOP`
+//! mode and the system clock is restored from the `PLL` before returning, the
+//! [`Clocks`](crate::hal::rcc::Clocks) of the board stay valid.
+//!
+//! Bytes received by `USART6` while the clock is stopped are lost, the syslink parser discards the
+//! incomplete packet and synchronizes on the next one. `TIM5` is stopped as well, so
+//! [`MonoTimer`](crate::monotonic::MonoTimer) does not advance during `STOP` mode. The independent
+//! watchdog keeps running, if it is [started](crate::watchdog) use a wake-up timeout shorter than
+//! the watchdog timeout.
+use crate::hal::pac::{EXTI, PWR, RCC, RTC, SYSCFG};
+use crate::motor::Motors;
+use cortex_m::peripheral::SCB;
+
+/// `EXTI` line of the `USART6` receive pin (`PC7`)
+const SYSLINK_EXTI_LINE: u32 = 7;
+/// `EXTI` line connected to the `RTC` wake-up timer
+const RTC_WAKEUP_EXTI_LINE: u32 = 22;
+/// Port index of `GPIOC` in the `SYSCFG` external interrupt configuration
+const EXTI_PORT_C: u32 = 2;
+/// Frequency of the `RTC` wake-up timer in Hz (`LSI` divided by 16)
+const WAKEUP_TIMER_HZ: u32 = 32_000 / 16;
+/// Longest supported wake-up timeout in milliseconds
+pub const MAX_TIMEOUT_MS: u32 = (1 << 16) * 1000 / WAKEUP_TIMER_HZ;
+/// `SLEEPDEEP` bit of the system control register
+const SCB_SCR_SLEEPDEEP: u32 = 1 << 2;
+
+/// Reason for leaving `STOP` mode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WakeReason {
+    /// Activity on the syslink receive line
+    Syslink,
+    /// The wake-up timeout expired
+    Timer,
+    /// Any other event or interrupt
+    Other,
+}
+
+/// Control of the low-power modes
+pub struct LowPower {
+    pwr: PWR,
+    rtc: RTC,
+}
+
+impl LowPower {
+    /// Prepare the wake-up sources
+    ///
+    /// This starts the `LSI` oscillator and selects it as the `RTC` clock, unless the `RTC` is
+    /// already clocked from another source. The `USART6` receive pin is configured as a wake-up
+    /// event, the pin itself stays connected to `USART6`.
+    pub fn new(pwr: PWR, rtc: RTC) -> Self {
+        // Safety: Only the bits related to the power controller and RTC are changed
+        let rcc = unsafe { &*RCC::ptr() };
+        rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+        // Allow write access to the backup domain, where the RTC configuration is kept
+        pwr.cr.modify(|_, w| w.dbp().set_bit());
+        rcc.csr.modify(|_, w| w.lsion().set_bit());
+        while rcc.csr.read().lsirdy().bit_is_clear() {}
+        if rcc.bdcr.read().rtcsel().bits() == 0 {
+            #[allow(unused_unsafe)]
+            rcc.bdcr.modify(|_, w| unsafe { w.rtcsel().bits(0b10) });
+        }
+        rcc.bdcr.modify(|_, w| w.rtcen().set_bit());
+        cortex_m::interrupt::free(|_| {
+            // Safety: Only the bits of the syslink and RTC wake-up lines are changed inside a
+            // critical section, the SYSCFG clock is enabled when the board is initialized
+            let syscfg = unsafe { &*SYSCFG::ptr() };
+            let exti = unsafe { &*EXTI::ptr() };
+            let shift = 4 * (SYSLINK_EXTI_LINE % 4);
+            syscfg.exticr2.modify(|r, w| unsafe {
+                w.bits((r.bits() & !(0xF << shift)) | (EXTI_PORT_C << shift))
+            });
+            // The start bit of a byte is a falling edge on the idle high line
+            let syslink = 1 << SYSLINK_EXTI_LINE;
+            let rtc_wakeup = 1 << RTC_WAKEUP_EXTI_LINE;
+            exti.ftsr.modify(|r, w| unsafe { w.bits(r.bits() | syslink) });
+            exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() | rtc_wakeup) });
+            exti.emr.modify(|r, w| unsafe { w.bits(r.bits() | syslink | rtc_wakeup) });
+        });
+        LowPower { pwr, rtc }
+    }
+
+    /// Enter `STOP` mode until syslink activity or, if given, until `timeout_ms` has passed
+    ///
+    /// The motors are stopped and disabled first and are not enabled again on wake-up. The
+    /// timeout is limited to [`MAX_TIMEOUT_MS`] and is only accurate to the tolerance of the
+    /// `LSI` oscillator.
+    pub fn stop(&mut self, motors: &mut Motors, timeout_ms: Option<u32>) -> WakeReason {
+        motors.stop();
+        motors.disable();
+        // Safety: The EXTI mask and pending bits of the wake-up lines are only used by this module
+        let exti = unsafe { &*EXTI::ptr() };
+        let syslink = 1 << SYSLINK_EXTI_LINE;
+        let wake_lines = syslink | (1 << RTC_WAKEUP_EXTI_LINE);
+        #[allow(unused_unsafe)]
+        exti.pr.write(|w| unsafe { w.bits(wake_lines) });
+        // The pending bit is only set for lines unmasked in IMR, an event line alone wakes the
+        // core without leaving a trace. The syslink line is unmasked while stopped so that the
+        // wake-up source can be read back, the `EXTI9_5` interrupt is not taken as long as it is
+        // masked in the NVIC. It is masked again afterwards so that UART traffic does not keep
+        // the interrupt pending for applications using other lines of `EXTI9_5`.
+        cortex_m::interrupt::free(|_| {
+            #[allow(unused_unsafe)]
+            exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | syslink) });
+        });
+        if let Some(timeout) = timeout_ms {
+            self.start_wakeup_timer(timeout);
+        }
+        // Use the low-power regulator and power down the flash in STOP mode
+        self.pwr
+            .cr
+            .modify(|_, w| w.pdds().clear_bit().lpds().set_bit().fpds().set_bit());
+        // Safety: Only the SLEEPDEEP bit is changed, the SCB is not used elsewhere in the crate
+        let scb = unsafe { &*SCB::ptr() };
+        unsafe { scb.scr.modify(|r| r | SCB_SCR_SLEEPDEEP) };
+        // Clear a possibly pending event so that the second `wfe` actually waits
+        cortex_m::asm::sev();
+        cortex_m::asm::wfe();
+        cortex_m::asm::wfe();
+        unsafe { scb.scr.modify(|r| r & !SCB_SCR_SLEEPDEEP) };
+        restore_clocks();
+        let reason = if self.rtc.isr.read().wutf().bit_is_set() {
+            WakeReason::Timer
+        } else if exti.pr.read().bits() & syslink != 0 {
+            WakeReason::Syslink
+        } else {
+            WakeReason::Other
+        };
+        cortex_m::interrupt::free(|_| {
+            #[allow(unused_unsafe)]
+            exti.imr.modify(|r, w| unsafe { w.bits(r.bits() & !syslink) });
+        });
+        self.stop_wakeup_timer();
+        #[allow(unused_unsafe)]
+        exti.pr.write(|w| unsafe { w.bits(wake_lines) });
+        reason
+    }
+
+    /// Give back the underlying peripherals
+    pub fn free(self) -> (PWR, RTC) {
+        (self.pwr, self.rtc)
+    }
+
+    /// Start the `RTC` wake-up timer
+    fn start_wakeup_timer(&mut self, timeout_ms: u32) {
+        let ticks = (timeout_ms.min(MAX_TIMEOUT_MS) * WAKEUP_TIMER_HZ / 1000).max(1) - 1;
+        self.unlock_rtc();
+        self.rtc.cr.modify(|_, w| w.wute().clear_bit());
+        while self.rtc.isr.read().wutwf().bit_is_clear() {}
+        #[allow(unused_unsafe)]
+        self.rtc.wutr.write(|w| unsafe { w.wut().bits(ticks as u16) });
+        self.rtc.isr.modify(|_, w| w.wutf().clear_bit());
+        // Clock the wake-up timer with RTCCLK / 16
+        #[allow(unused_unsafe)]
+        self.rtc.cr.modify(|_, w| unsafe {
+            w.wucksel().bits(0b000).wutie().set_bit().wute().set_bit()
+        });
+        self.lock_rtc();
+    }
+
+    /// Stop the `RTC` wake-up timer and clear its flag
+    fn stop_wakeup_timer(&mut self) {
+        self.unlock_rtc();
+        self.rtc
+            .cr
+            .modify(|_, w| w.wutie().clear_bit().wute().clear_bit());
+        self.rtc.isr.modify(|_, w| w.wutf().clear_bit());
+        self.lock_rtc();
+    }
+
+    /// Remove the write protection of the `RTC` registers
+    #[allow(unused_unsafe)]
+    fn unlock_rtc(&mut self) {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xCA) });
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0x53) });
+    }
+
+    /// Enable the write protection of the `RTC` registers
+    #[allow(unused_unsafe)]
+    fn lock_rtc(&mut self) {
+        self.rtc.wpr.write(|w| unsafe { w.key().bits(0xFF) });
+    }
+}
+
+/// Restart the `HSE` and `PLL` after `STOP` mode and switch the system clock back to the `PLL`
+///
+/// The system runs from `HSI` after leaving `STOP` mode. The `PLL` configuration, bus prescalers
+/// and flash latency are kept in `STOP` mode so the frozen clocks are valid again once the `PLL` is
+/// selected.
+fn restore_clocks() {
+    // Safety: Restores the clock configuration previously set up when the clocks were frozen
+    let rcc = unsafe { &*RCC::ptr() };
+    if rcc.pllcfgr.read().pllsrc().bit_is_set() {
+        rcc.cr.modify(|_, w| w.hseon().set_bit());
+        while rcc.cr.read().hserdy().bit_is_clear() {}
+    }
+    rcc.cr.modify(|_, w| w.pllon().set_bit());
+    while rcc.cr.read().pllrdy().bit_is_clear() {}
+    rcc.cfgr.modify(|_, w| w.sw().pll());
+    while !rcc.cfgr.read().sws().is_pll() {}
+}
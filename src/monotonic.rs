@@ -0,0 +1,153 @@
+//! Free-running microsecond timer
+//!
+//! `TIM5` is reserved as a 32-bit monotonic clock counting microseconds since it was started.
+//! Counter overflows are extended in software to give 64-bit timestamps. `TIM5` does not collide
+//! with the timers used by the [`motors`](crate::motor) (`TIM2` and `TIM4`).
+//!
+//! # Usage
+//! Create [`MonoTimer`] once and call [`MonoTimer::now`] to get the current timestamp. The 32-bit
+//! counter overflows roughly every 71 minutes, to not lose track of overflows either call
+//! [`MonoTimer::now`] more often than that or call [`MonoTimer::on_interrupt`] from the `TIM5`
+//! interrupt.
+//!
+//! With the `rtic` feature [`MonoTimer`] implements
+//! [`rtic_monotonic::Monotonic`](https://docs.rs/rtic-monotonic) so that it can be used as the
+//! monotonic of an RTIC application.
+use crate::hal::pac::{RCC, TIM5};
+use crate::hal::rcc::Clocks;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Frequency of the timer ticks in Hz
+pub const TICK_HZ: u32 = 1_000_000;
+
+// Flags of the status register, they are cleared by writing zero while writing one has no effect.
+// Clearing a flag with a read-modify-write could also clear a flag set in between.
+const SR_UIF: u32 = 1 << 0;
+#[cfg(feature = "rtic")]
+const SR_CC1IF: u32 = 1 << 1;
+
+/// Monotonic microsecond clock based on `TIM5`
+pub struct MonoTimer {
+    tim: TIM5,
+    overflows: AtomicU32,
+}
+
+impl MonoTimer {
+    /// Start the free-running timer
+    pub fn new(tim: TIM5, clocks: Clocks) -> Self {
+        // Safety: Only the bits related to TIM5 are changed
+        unsafe {
+            let rcc = &*RCC::ptr();
+            rcc.apb1enr.modify(|_, w| w.tim5en().set_bit());
+            rcc.apb1rstr.modify(|_, w| w.tim5rst().set_bit());
+            rcc.apb1rstr.modify(|_, w| w.tim5rst().clear_bit());
+        }
+        // Timers on APB1 run at twice the bus frequency if the bus is prescaled
+        let timer_clock = if clocks.ppre1() == 1 {
+            clocks.pclk1().0
+        } else {
+            clocks.pclk1().0 * 2
+        };
+        let psc = (timer_clock / TICK_HZ - 1) as u16;
+        tim.psc.write(|w| w.psc().bits(psc));
+        #[allow(unused_unsafe)]
+        tim.arr.write(|w| unsafe { w.bits(u32::MAX) });
+        // Trigger an update event to load the prescaler and clear the resulting flag
+        tim.egr.write(|w| w.ug().set_bit());
+        #[allow(unused_unsafe)]
+        tim.sr.write(|w| unsafe { w.bits(!SR_UIF) });
+        tim.dier.modify(|_, w| w.uie().set_bit());
+        tim.cr1.modify(|_, w| w.cen().set_bit());
+        MonoTimer {
+            tim,
+            overflows: AtomicU32::new(0),
+        }
+    }
+
+    /// Current time in microseconds since the timer was started
+    pub fn now(&self) -> u64 {
+        cortex_m::interrupt::free(|_| {
+            let mut low = self.tim.cnt.read().bits();
+            if self.handle_overflow() {
+                // The counter could have wrapped after it was read above
+                low = self.tim.cnt.read().bits();
+            }
+            ((self.overflows.load(Ordering::Relaxed) as u64) << 32) | low as u64
+        })
+    }
+
+    /// Handle pending `TIM5` interrupts
+    ///
+    /// This should be called from the `TIM5` interrupt handler.
+    pub fn on_interrupt(&self) {
+        cortex_m::interrupt::free(|_| {
+            self.handle_overflow();
+        });
+    }
+
+    /// Record a pending counter overflow, must be called in a critical section
+    fn handle_overflow(&self) -> bool {
+        if self.tim.sr.read().uif().bit_is_set() {
+            #[allow(unused_unsafe)]
+            self.tim.sr.write(|w| unsafe { w.bits(!SR_UIF) });
+            let overflows = self.overflows.load(Ordering::Relaxed);
+            self.overflows.store(overflows.wrapping_add(1), Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Stop the timer and release the underlying peripheral
+    pub fn free(self) -> TIM5 {
+        self.tim.cr1.modify(|_, w| w.cen().clear_bit());
+        self.tim.dier.reset();
+        self.tim
+    }
+}
+
+#[cfg(feature = "rtic")]
+impl rtic_monotonic::Monotonic for MonoTimer {
+    // The overflow interrupt is needed to keep the 64-bit time up to date
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    type Instant = fugit::TimerInstantU64<TICK_HZ>;
+    type Duration = fugit::TimerDurationU64<TICK_HZ>;
+
+    fn now(&mut self) -> Self::Instant {
+        Self::Instant::from_ticks(MonoTimer::now(self))
+    }
+
+    fn set_compare(&mut self, instant: Self::Instant) {
+        // Instants further away than one counter period trigger early, RTIC will then set the
+        // compare value again
+        #[allow(unused_unsafe)]
+        self.tim
+            .ccr1
+            .write(|w| unsafe { w.bits(instant.ticks() as u32) });
+    }
+
+    fn clear_compare_flag(&mut self) {
+        #[allow(unused_unsafe)]
+        self.tim.sr.write(|w| unsafe { w.bits(!SR_CC1IF) });
+    }
+
+    fn zero() -> Self::Instant {
+        Self::Instant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        cortex_m::interrupt::free(|_| {
+            #[allow(unused_unsafe)]
+            self.tim.cnt.write(|w| unsafe { w.bits(0) });
+            #[allow(unused_unsafe)]
+            self.tim.sr.write(|w| unsafe { w.bits(!SR_UIF) });
+            self.overflows.store(0, Ordering::Relaxed);
+        });
+        self.tim.dier.modify(|_, w| w.cc1ie().set_bit());
+    }
+
+    fn on_interrupt(&mut self) {
+        MonoTimer::on_interrupt(self);
+    }
+}
@@ -173,3 +173,24 @@ impl Motors {
         self.m4.set_duty(0);
     }
 }
+
+/// Stop all motors without access to [`Motors`]
+///
+/// This is used in situations where the owner of [`Motors`] can no longer be trusted, such as in
+/// a panic handler, and writes directly to the timer registers.
+///
+/// # Safety
+/// This steals access to `TIM2` and `TIM4` and should only be used when normal execution can not
+/// continue.
+#[cfg(feature = "panic_handler")]
+pub(crate) unsafe fn emergency_stop() {
+    let tim2 = &*TIM2::ptr();
+    let tim4 = &*TIM4::ptr();
+    // Zero duty cycle on all motor channels before disabling the outputs
+    tim2.ccr1.reset();
+    tim2.ccr2.reset();
+    tim2.ccr4.reset();
+    tim4.ccr4.reset();
+    tim2.ccer.reset();
+    tim4.ccer.reset();
+}
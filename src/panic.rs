@@ -0,0 +1,217 @@
+//! Panic handler which reports panics on the LEDs and persists them across resets
+//!
+//! When enabled with the `panic_handler` feature this module provides the `#[panic_handler]` for
+//! the application. On panic the motors are stopped, the panic location and message are stored in
+//! backup SRAM and the two red LEDs blink rapidly until the Crazyflie is reset.
+//!
+//! # Usage
+//! After a reset call [`take_report`] to retrieve information about the last panic, this can then
+//! be reported over [`syslink`](crate::uart_syslink) or any other console.
+//!
+//! Backup SRAM is retained across resets, but not when power is removed.
+use crate::clock;
+use crate::hal::pac::{self, PWR, RCC};
+use crate::hal::prelude::*;
+use crate::led::{LedN, Leds};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Start of backup SRAM
+const BKPSRAM_ADDRESS: usize = 0x4002_4000;
+/// Magic number marking a valid panic record in backup SRAM
+const PANIC_MAGIC: u32 = 0x5041_4e43;
+/// Maximum number of bytes stored of the file name of the panic location
+const FILE_LEN: usize = 64;
+/// Maximum number of bytes stored of the panic message
+const MESSAGE_LEN: usize = 256;
+/// Frequency of the internal oscillator in MHz, which is used until the clocks are configured
+const HSI_MHZ: u32 = 16;
+/// Number of LED toggles per second
+const BLINK_TOGGLES_PER_SECOND: u32 = 10;
+
+/// Set when the panic handler is entered to detect a panic while handling a panic
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Information about a panic as stored in backup SRAM
+#[derive(Clone)]
+#[repr(C)]
+pub struct PanicReport {
+    magic: u32,
+    line: u32,
+    column: u32,
+    file_len: u32,
+    file: [u8; FILE_LEN],
+    message_len: u32,
+    message: [u8; MESSAGE_LEN],
+}
+
+impl PanicReport {
+    /// Line number of the panic location
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Column of the panic location
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    /// File of the panic location, possibly truncated
+    pub fn file(&self) -> &str {
+        valid_str(&self.file[..(self.file_len as usize).min(FILE_LEN)])
+    }
+
+    /// Formatted panic message without the location, possibly truncated
+    pub fn message(&self) -> &str {
+        valid_str(&self.message[..(self.message_len as usize).min(MESSAGE_LEN)])
+    }
+}
+
+impl fmt::Debug for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PanicReport")
+            .field("file", &self.file())
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+/// Return the longest valid UTF-8 prefix of `bytes`
+///
+/// Since messages are truncated to fit in backup SRAM the last character could be cut short.
+fn valid_str(bytes: &[u8]) -> &str {
+    match str::from_utf8(bytes) {
+        Ok(s) => s,
+        // Unwrap safety: The slice is valid up to the reported position
+        Err(e) => str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+    }
+}
+
+/// Writer which fills a fixed size buffer, silently truncating the output
+struct Truncating<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let count = bytes.len().min(self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Enable access to backup SRAM
+///
+/// # Safety
+/// Modifies `RCC` and `PWR` registers which might be owned elsewhere, only bits related to backup
+/// SRAM are changed.
+unsafe fn enable_backup_sram() {
+    let rcc = &*RCC::ptr();
+    let pwr = &*PWR::ptr();
+    rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+    rcc.ahb1enr.modify(|_, w| w.bkpsramen().set_bit());
+}
+
+/// Retrieve and clear the report of the last panic, if any
+pub fn take_report() -> Option<PanicReport> {
+    let record = BKPSRAM_ADDRESS as *mut PanicReport;
+    unsafe {
+        enable_backup_sram();
+        if ptr::read_volatile(ptr::addr_of!((*record).magic)) != PANIC_MAGIC {
+            return None;
+        }
+        let report = ptr::read_volatile(record);
+        ptr::write_volatile(ptr::addr_of_mut!((*record).magic), 0);
+        Some(report)
+    }
+}
+
+/// Store information about a panic in backup SRAM
+fn persist(info: &PanicInfo) {
+    let mut report = PanicReport {
+        magic: PANIC_MAGIC,
+        line: 0,
+        column: 0,
+        file_len: 0,
+        file: [0; FILE_LEN],
+        message_len: 0,
+        message: [0; MESSAGE_LEN],
+    };
+    if let Some(location) = info.location() {
+        report.line = location.line();
+        report.column = location.column();
+        let mut file = Truncating {
+            buffer: &mut report.file,
+            len: 0,
+        };
+        let _ = file.write_str(location.file());
+        report.file_len = file.len as u32;
+    }
+    let mut message = Truncating {
+        buffer: &mut report.message,
+        len: 0,
+    };
+    let _ = write!(message, "{}", info.message());
+    report.message_len = message.len as u32;
+    unsafe {
+        enable_backup_sram();
+        ptr::write_volatile(BKPSRAM_ADDRESS as *mut PanicReport, report);
+    }
+}
+
+/// Number of CPU cycles between LED toggles at the current system clock
+///
+/// A panic can happen before [`clock::configure`] has been called, so the clock source is read
+/// back instead of assuming the configured frequency.
+fn blink_cycles() -> u32 {
+    // Safety: Only reads the clock configuration
+    let rcc = unsafe { &*RCC::ptr() };
+    let sws = rcc.cfgr.read().sws();
+    let sysclk_mhz = if sws.is_pll() {
+        clock::SYSCLK_MHZ
+    } else if sws.is_hse() {
+        clock::HSE_MHZ
+    } else {
+        HSI_MHZ
+    };
+    sysclk_mhz * 1_000_000 / BLINK_TOGGLES_PER_SECOND
+}
+
+#[inline(never)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    // A panic while persisting, e.g. from a `Display` implementation used in the message, ends up
+    // here again. The motors are already stopped then and the report is skipped.
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        // Safety: Normal execution has stopped so nobody else will use the motor timers
+        unsafe { crate::motor::emergency_stop() };
+        persist(info);
+    }
+    // Safety: Interrupts are disabled and the application will never run again so we take over
+    // the LED pins
+    let dp = unsafe { pac::Peripherals::steal() };
+    let gpioc = dp.GPIOC.split();
+    let gpiod = dp.GPIOD.split();
+    let mut leds = Leds::new(gpioc.pc0, gpioc.pc1, gpioc.pc2, gpioc.pc3, gpiod.pd2);
+    // The on-board LEDs can not fail so errors are ignored
+    let _ = leds.clear_all();
+    let cycles = blink_cycles();
+    loop {
+        let _ = leds[LedN::RedLeft].on();
+        let _ = leds[LedN::RedRight].on();
+        cortex_m::asm::delay(cycles);
+        let _ = leds[LedN::RedLeft].off();
+        let _ = leds[LedN::RedRight].off();
+        cortex_m::asm::delay(cycles);
+    }
+}
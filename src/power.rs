@@ -0,0 +1,225 @@
+//! Power management through the `nRF51`
+//!
+//! The `nRF51` controls the power of the Crazyflie and measures the battery. This module builds
+//! the syslink power management requests and keeps track of the decoded answers.
+//!
+//! # Usage
+//! Pass a [`Battery`] to [`dispatch`](crate::uart_syslink::dispatch::dispatch) with every received
+//! packet and send [`enable_auto_update`] once so that the `nRF51` reports the battery state
+//! periodically. [`Battery::voltage`] then gives the voltage needed by
+//! [`Motor::set_ratio`](crate::motor::Motor::set_ratio).
+use crate::logging::{LogValue, LogVariable, Loggable};
+use crate::uart_syslink::dispatch::Handler;
+use crate::uart_syslink::message::{pm, BatteryPayload, Power};
+
+/// Charging flag of the battery state
+const FLAG_CHARGING: u8 = 1 << 0;
+/// Flag of the battery state signalling that USB power is good
+const FLAG_USB_POWER_GOOD: u8 = 1 << 1;
+
+/// State of the battery charger
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChargeState {
+    /// Running from battery
+    Battery,
+    /// USB connected and charging
+    Charging,
+    /// USB connected and charging has completed
+    Charged,
+}
+
+/// Battery state as reported by the `nRF51`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BatteryState {
+    /// The battery is being charged
+    pub charging: bool,
+    /// USB power is connected
+    pub usb_connected: bool,
+    /// Battery voltage in volts
+    pub voltage: f32,
+    /// Charge current in amperes
+    pub charge_current: f32,
+}
+
+impl BatteryState {
+    /// State of the battery charger
+    pub fn charge_state(&self) -> ChargeState {
+        match (self.usb_connected, self.charging) {
+            (_, true) => ChargeState::Charging,
+            (true, false) => ChargeState::Charged,
+            (false, false) => ChargeState::Battery,
+        }
+    }
+}
+
+impl From<BatteryPayload> for BatteryState {
+    fn from(payload: BatteryPayload) -> Self {
+        BatteryState {
+            charging: payload.flags & FLAG_CHARGING != 0,
+            usb_connected: payload.flags & FLAG_USB_POWER_GOOD != 0,
+            voltage: payload.voltage,
+            charge_current: payload.charge_current,
+        }
+    }
+}
+
+/// Request periodic battery state updates from the `nRF51`
+pub fn enable_auto_update() -> syslink::Packet {
+    syslink::Packet::new(pm::BATTERY_AUTOUPDATE, &[])
+}
+
+/// Request the current battery voltage from the `nRF51`
+pub fn request_voltage() -> syslink::Packet {
+    syslink::Packet::new(pm::BATTERY_VOLTAGE, &[])
+}
+
+/// Power off the whole Crazyflie
+///
+/// Once the `nRF51` receives this packet the `STM32F405` loses power, make sure the motors are
+/// stopped first.
+pub fn switch_off() -> syslink::Packet {
+    syslink::Packet::new(pm::ONOFF_SWITCHOFF, &[])
+}
+
+/// Latest power information received from the `nRF51`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Battery {
+    state: Option<BatteryState>,
+    voltage: Option<f32>,
+    switching_off: bool,
+}
+
+impl Battery {
+    /// Create without any received information
+    pub fn new() -> Self {
+        Battery::default()
+    }
+
+    /// Latest battery voltage in volts
+    pub fn voltage(&self) -> Option<f32> {
+        self.voltage
+    }
+
+    /// Latest battery state
+    pub fn state(&self) -> Option<BatteryState> {
+        self.state
+    }
+
+    /// Check if USB power is connected
+    pub fn usb_connected(&self) -> bool {
+        self.state.map_or(false, |s| s.usb_connected)
+    }
+
+    /// Check if the `nRF51` announced that it is switching off the Crazyflie
+    pub fn switching_off(&self) -> bool {
+        self.switching_off
+    }
+}
+
+impl Handler for Battery {
+    fn power(&mut self, message: Power) {
+        match message {
+            Power::BatteryVoltage(voltage) => self.voltage = Some(voltage),
+            Power::BatteryState(payload) => {
+                self.voltage = Some(payload.voltage);
+                self.state = Some(payload.into());
+            }
+            Power::SwitchOff => self.switching_off = true,
+            _ => {}
+        }
+    }
+}
+
+impl Loggable for Battery {
+    fn log(&self, f: &mut dyn FnMut(LogVariable)) {
+        if let Some(voltage) = self.voltage {
+            f(LogVariable {
+                group: "pm",
+                name: "vbat",
+                value: LogValue::F32(voltage),
+            });
+        }
+        if let Some(state) = self.state {
+            f(LogVariable {
+                group: "pm",
+                name: "chargeCurrent",
+                value: LogValue::F32(state.charge_current),
+            });
+            f(LogVariable {
+                group: "pm",
+                name: "state",
+                value: LogValue::U8(state.charge_state() as u8),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart_syslink::dispatch::dispatch;
+
+    /// Battery state packet as sent by the `nRF51`: flags, voltage and charge current
+    fn battery_state(flags: u8, voltage: f32, charge_current: f32) -> syslink::Packet {
+        let mut data = [0u8; 9];
+        data[0] = flags;
+        data[1..5].copy_from_slice(&voltage.to_le_bytes());
+        data[5..9].copy_from_slice(&charge_current.to_le_bytes());
+        syslink::Packet::new(pm::BATTERY_STATE, &data)
+    }
+
+    fn receive(packet: syslink::Packet) -> Battery {
+        let mut battery = Battery::new();
+        assert_eq!(dispatch(&packet, &mut [&mut battery]), Ok(()));
+        battery
+    }
+
+    #[test]
+    fn decodes_battery_state() {
+        let battery = receive(battery_state(
+            FLAG_CHARGING | FLAG_USB_POWER_GOOD,
+            4.1,
+            0.25,
+        ));
+        assert_eq!(battery.voltage(), Some(4.1));
+        assert!(battery.usb_connected());
+        assert_eq!(
+            battery.state(),
+            Some(BatteryState {
+                charging: true,
+                usb_connected: true,
+                voltage: 4.1,
+                charge_current: 0.25,
+            })
+        );
+    }
+
+    #[test]
+    fn charge_state_from_flags() {
+        let charge_state = |flags| {
+            receive(battery_state(flags, 3.9, 0.0))
+                .state()
+                .map(|s| s.charge_state())
+        };
+        assert_eq!(charge_state(0), Some(ChargeState::Battery));
+        assert_eq!(
+            charge_state(FLAG_CHARGING | FLAG_USB_POWER_GOOD),
+            Some(ChargeState::Charging)
+        );
+        assert_eq!(
+            charge_state(FLAG_USB_POWER_GOOD),
+            Some(ChargeState::Charged)
+        );
+    }
+
+    #[test]
+    fn voltage_and_switch_off() {
+        let battery = receive(syslink::Packet::new(
+            pm::BATTERY_VOLTAGE,
+            &3.7f32.to_le_bytes(),
+        ));
+        assert_eq!(battery.voltage(), Some(3.7));
+        assert_eq!(battery.state(), None);
+        assert!(receive(switch_off()).switching_off());
+    }
+}
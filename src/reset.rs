@@ -0,0 +1,182 @@
+//! Reset-cause detection and boot diagnostics
+//!
+//! # Usage
+//! Read the cause of the last reset with [`ResetCause::read_and_clear`] before the `RCC` is
+//! constrained, [`Board`](crate::board::Board) does this automatically. With the `eeprom` feature
+//! the cause can be appended to the [`ResetHistory`] stored in EEPROM to debug unexplained
+//! reboots in the field.
+use crate::hal::pac::RCC;
+
+/// Cause of the last reset as reported by the `RCC` reset flags
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ResetCause {
+    /// No reset flag was set
+    Unknown = 0,
+    /// Power-on or power-down reset
+    PowerOn = 1,
+    /// Supply voltage dropped below the brownout threshold
+    Brownout = 2,
+    /// The independent watchdog expired
+    IndependentWatchdog = 3,
+    /// The window watchdog expired
+    WindowWatchdog = 4,
+    /// Reset requested by software
+    Software = 5,
+    /// Illegal entry into low-power mode
+    LowPower = 6,
+    /// The `NRST` pin was pulled low
+    Pin = 7,
+}
+
+impl ResetCause {
+    /// Read the reset flags and clear them so that the next reset is reported correctly
+    ///
+    /// Several flags are set for a single reset (e.g. a power-on reset also sets the brownout and
+    /// pin flags), the most specific cause is reported.
+    pub fn read_and_clear(rcc: &RCC) -> Self {
+        let csr = rcc.csr.read();
+        let cause = if csr.lpwrrstf().bit_is_set() {
+            ResetCause::LowPower
+        } else if csr.wwdgrstf().bit_is_set() {
+            ResetCause::WindowWatchdog
+        } else if csr.wdgrstf().bit_is_set() {
+            ResetCause::IndependentWatchdog
+        } else if csr.sftrstf().bit_is_set() {
+            ResetCause::Software
+        } else if csr.porrstf().bit_is_set() {
+            ResetCause::PowerOn
+        } else if csr.borrstf().bit_is_set() {
+            ResetCause::Brownout
+        } else if csr.padrstf().bit_is_set() {
+            ResetCause::Pin
+        } else {
+            ResetCause::Unknown
+        };
+        rcc.csr.modify(|_, w| w.rmvf().set_bit());
+        cause
+    }
+
+    /// Convert from the raw value stored in the [`ResetHistory`]
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ResetCause::PowerOn,
+            2 => ResetCause::Brownout,
+            3 => ResetCause::IndependentWatchdog,
+            4 => ResetCause::WindowWatchdog,
+            5 => ResetCause::Software,
+            6 => ResetCause::LowPower,
+            7 => ResetCause::Pin,
+            _ => ResetCause::Unknown,
+        }
+    }
+
+    /// Check if the reset was unexpected, i.e. caused by a watchdog or brownout
+    pub fn is_fault(self) -> bool {
+        matches!(
+            self,
+            ResetCause::Brownout
+                | ResetCause::IndependentWatchdog
+                | ResetCause::WindowWatchdog
+                | ResetCause::LowPower
+        )
+    }
+}
+
+#[cfg(feature = "eeprom")]
+pub use self::history::*;
+
+#[cfg(feature = "eeprom")]
+mod history {
+    use super::ResetCause;
+    use crate::eeprom::{Eeprom, Error, RESET_HISTORY_ADDRESS};
+
+    /// Number of reset causes kept in the history
+    pub const HISTORY_LEN: usize = 16;
+    /// Marker of a valid reset history
+    const MAGIC: [u8; 2] = *b"RH";
+    /// Size of the serialized history, must fit in a single EEPROM page
+    const SIZE: usize = 2 + 4 + 1 + HISTORY_LEN;
+
+    /// The most recent reset causes stored in EEPROM
+    #[derive(Clone, Debug)]
+    pub struct ResetHistory {
+        boot_count: u32,
+        next: usize,
+        causes: [u8; HISTORY_LEN],
+    }
+
+    impl ResetHistory {
+        /// Create an empty history
+        pub fn new() -> Self {
+            ResetHistory {
+                boot_count: 0,
+                next: 0,
+                causes: [ResetCause::Unknown as u8; HISTORY_LEN],
+            }
+        }
+
+        /// Read the history from EEPROM, an empty history is returned if none is stored
+        pub fn read(eeprom: &mut Eeprom) -> Result<Self, Error> {
+            let mut data = [0u8; SIZE];
+            eeprom.read_data(RESET_HISTORY_ADDRESS, &mut data)?;
+            if data[..2] != MAGIC || data[6] as usize >= HISTORY_LEN {
+                return Ok(ResetHistory::new());
+            }
+            let mut causes = [0u8; HISTORY_LEN];
+            causes.copy_from_slice(&data[7..]);
+            Ok(ResetHistory {
+                boot_count: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+                next: data[6] as usize,
+                causes,
+            })
+        }
+
+        /// Write the history to EEPROM
+        ///
+        /// The EEPROM is busy for a few milliseconds after writing, subsequent accesses will fail
+        /// during this time.
+        pub fn write(&self, eeprom: &mut Eeprom) -> Result<(), Error> {
+            let mut data = [0u8; SIZE];
+            data[..2].copy_from_slice(&MAGIC);
+            data[2..6].copy_from_slice(&self.boot_count.to_le_bytes());
+            data[6] = self.next as u8;
+            data[7..].copy_from_slice(&self.causes);
+            eeprom.write_page(RESET_HISTORY_ADDRESS, &data)
+        }
+
+        /// Add a reset cause to the history, replacing the oldest entry
+        pub fn push(&mut self, cause: ResetCause) {
+            self.causes[self.next] = cause as u8;
+            self.next = (self.next + 1) % HISTORY_LEN;
+            self.boot_count = self.boot_count.wrapping_add(1);
+        }
+
+        /// Total number of recorded boots
+        pub fn boot_count(&self) -> u32 {
+            self.boot_count
+        }
+
+        /// Iterate over the recorded reset causes, most recent first
+        pub fn iter(&self) -> impl Iterator<Item = ResetCause> + '_ {
+            let recorded = (self.boot_count as usize).min(HISTORY_LEN);
+            (1..=recorded).map(move |i| {
+                ResetCause::from_u8(self.causes[(self.next + HISTORY_LEN - i) % HISTORY_LEN])
+            })
+        }
+    }
+
+    impl Default for ResetHistory {
+        fn default() -> Self {
+            ResetHistory::new()
+        }
+    }
+
+    /// Append a reset cause to the history in EEPROM and return the updated history
+    pub fn record(eeprom: &mut Eeprom, cause: ResetCause) -> Result<ResetHistory, Error> {
+        let mut history = ResetHistory::read(eeprom)?;
+        history.push(cause);
+        history.write(eeprom)?;
+        Ok(history)
+    }
+}
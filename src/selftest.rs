@@ -0,0 +1,396 @@
+//! Boot-time self-tests with reportable results
+//!
+//! Each subsystem provides a [`SelfTest`] and [`run`] executes all of them, collecting the results
+//! in a [`Report`]. The report can be shown on the [status LEDs](crate::status) and
+//! [serialized](Report::write) into a buffer. This crate does not answer radio requests itself,
+//! the application sends the serialized report wherever it needs to, e.g. as the payload of a
+//! radio packet.
+//!
+//! Tests for the on-board hardware are provided in this module: [`EepromMagic`], [`ChipId`],
+//! [`SyslinkRoundTrip`] and [`MotorPwm`].
+#[cfg(feature = "eeprom")]
+use crate::eeprom::{ConfigBlock, ConfigError, Eeprom};
+use crate::motor::{Motor, Motors};
+use crate::sensors;
+use crate::status::{Status, StatusLeds};
+#[cfg(feature = "uart_syslink")]
+use crate::uart_syslink::{message::sys, UartComm};
+use embedded_hal::blocking::i2c::WriteRead;
+use embedded_hal::digital::v2::OutputPin;
+
+/// Maximum number of tests in a single [`Report`]
+pub const MAX_TESTS: usize = 16;
+
+/// Reason why a test failed
+pub type Reason = &'static str;
+
+/// A test of a single subsystem
+pub trait SelfTest {
+    /// Short name of the test
+    fn name(&self) -> &'static str;
+
+    /// Run the test, returning the reason for failure
+    fn run(&mut self) -> Result<(), Reason>;
+}
+
+/// Outcome of a single test
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    /// Name of the test
+    pub name: &'static str,
+    /// `Ok` if the test passed or the reason for the failure
+    pub outcome: Result<(), Reason>,
+}
+
+impl TestResult {
+    /// Check if the test passed
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+/// Results of running self-tests
+#[derive(Copy, Clone, Debug)]
+pub struct Report {
+    results: [Option<TestResult>; MAX_TESTS],
+    len: usize,
+}
+
+impl Report {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Report {
+            results: [None; MAX_TESTS],
+            len: 0,
+        }
+    }
+
+    /// Run a single test and add the result to the report
+    ///
+    /// If the report already holds [`MAX_TESTS`] results the test is not run and `false` is
+    /// returned.
+    pub fn run(&mut self, test: &mut dyn SelfTest) -> bool {
+        if self.len == MAX_TESTS {
+            return false;
+        }
+        self.results[self.len] = Some(TestResult {
+            name: test.name(),
+            outcome: test.run(),
+        });
+        self.len += 1;
+        true
+    }
+
+    /// Check if all tests passed
+    pub fn passed(&self) -> bool {
+        self.iter().all(|r| r.passed())
+    }
+
+    /// Iterate over the results in the order the tests were run
+    pub fn iter(&self) -> impl Iterator<Item = &TestResult> + '_ {
+        self.results[..self.len].iter().flatten()
+    }
+
+    /// Show the overall result on the status LEDs
+    pub fn show<P: OutputPin>(&self, leds: &mut StatusLeds<P>) {
+        if self.passed() {
+            leds.clear(Status::SelfTestFailed);
+            leds.set(Status::SelfTestPassed);
+        } else {
+            leds.clear(Status::SelfTestPassed);
+            leds.set(Status::SelfTestFailed);
+        }
+    }
+
+    /// Serialize the report into `buffer`
+    ///
+    /// The format is the number of tests followed by, for each test, the length of the name, the
+    /// name, `0` for pass or `1` for failure, and for failures the length of the reason followed
+    /// by the reason. Serialization stops at the first test which does not fit in `buffer`, the
+    /// number of tests is then the number actually written. Returns the number of bytes written.
+    pub fn write(&self, buffer: &mut [u8]) -> usize {
+        if buffer.is_empty() {
+            return 0;
+        }
+        let mut count = 0;
+        let mut pos = 1;
+        for result in self.iter() {
+            let name = &result.name.as_bytes()[..result.name.len().min(u8::MAX as usize)];
+            let reason = match result.outcome {
+                Ok(()) => &[][..],
+                Err(reason) => &reason.as_bytes()[..reason.len().min(u8::MAX as usize)],
+            };
+            let size = 2 + name.len() + if result.passed() { 0 } else { 1 + reason.len() };
+            if pos + size > buffer.len() {
+                break;
+            }
+            buffer[pos] = name.len() as u8;
+            buffer[pos + 1..pos + 1 + name.len()].copy_from_slice(name);
+            pos += 1 + name.len();
+            if result.passed() {
+                buffer[pos] = 0;
+                pos += 1;
+            } else {
+                buffer[pos] = 1;
+                buffer[pos + 1] = reason.len() as u8;
+                buffer[pos + 2..pos + 2 + reason.len()].copy_from_slice(reason);
+                pos += 2 + reason.len();
+            }
+            count += 1;
+        }
+        buffer[0] = count;
+        pos
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report::new()
+    }
+}
+
+/// Run all tests, in order, and collect the results
+pub fn run(tests: &mut [&mut dyn SelfTest]) -> Report {
+    let mut report = Report::new();
+    for test in tests.iter_mut() {
+        report.run(&mut **test);
+    }
+    report
+}
+
+/// Check that the EEPROM holds a valid config block
+#[cfg(feature = "eeprom")]
+pub struct EepromMagic<'a>(pub &'a mut Eeprom);
+
+#[cfg(feature = "eeprom")]
+impl SelfTest for EepromMagic<'_> {
+    fn name(&self) -> &'static str {
+        "eeprom"
+    }
+
+    fn run(&mut self) -> Result<(), Reason> {
+        match ConfigBlock::read(self.0) {
+            Ok(_) => Ok(()),
+            Err(ConfigError::Eeprom(_)) => Err("read failed"),
+            Err(ConfigError::Magic(_)) => Err("wrong magic"),
+            Err(ConfigError::Version(_)) => Err("unsupported version"),
+            Err(ConfigError::Checksum) => Err("wrong checksum"),
+        }
+    }
+}
+
+/// Check the chip ID of a sensor on an I2C bus
+pub struct ChipId<'a, I2C> {
+    name: &'static str,
+    i2c: &'a mut I2C,
+    address: u8,
+    register: u8,
+    expected: u8,
+}
+
+impl<'a, I2C: WriteRead> ChipId<'a, I2C> {
+    /// Check that reading `register` of the device at `address` returns `expected`
+    pub fn new(
+        name: &'static str,
+        i2c: &'a mut I2C,
+        address: u8,
+        register: u8,
+        expected: u8,
+    ) -> Self {
+        ChipId {
+            name,
+            i2c,
+            address,
+            register,
+            expected,
+        }
+    }
+
+    /// Check the `WHO_AM_I` of the `BMI088` accelerometer
+    pub fn bmi088_accel(i2c: &'a mut I2C) -> Self {
+        ChipId::new(
+            "bmi088 accel",
+            i2c,
+            sensors::BMI088_ACCEL_ADDRESS,
+            sensors::CHIP_ID_REGISTER,
+            sensors::BMI088_ACCEL_CHIP_ID,
+        )
+    }
+
+    /// Check the `WHO_AM_I` of the `BMI088` gyroscope
+    pub fn bmi088_gyro(i2c: &'a mut I2C) -> Self {
+        ChipId::new(
+            "bmi088 gyro",
+            i2c,
+            sensors::BMI088_GYRO_ADDRESS,
+            sensors::CHIP_ID_REGISTER,
+            sensors::BMI088_GYRO_CHIP_ID,
+        )
+    }
+
+    /// Check the chip ID of the `BMP388` barometer
+    pub fn bmp388(i2c: &'a mut I2C) -> Self {
+        ChipId::new(
+            "bmp388",
+            i2c,
+            sensors::BMP388_ADDRESS,
+            sensors::CHIP_ID_REGISTER,
+            sensors::BMP388_CHIP_ID,
+        )
+    }
+}
+
+impl<I2C: WriteRead> SelfTest for ChipId<'_, I2C> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&mut self) -> Result<(), Reason> {
+        let mut id = [0u8];
+        self.i2c
+            .write_read(self.address, &[self.register], &mut id)
+            .map_err(|_| "no response")?;
+        if id[0] == self.expected {
+            Ok(())
+        } else {
+            Err("wrong chip id")
+        }
+    }
+}
+
+/// Check that the `nRF51` answers a request over syslink
+#[cfg(feature = "uart_syslink")]
+pub struct SyslinkRoundTrip<'a, N> {
+    comm: &'a mut UartComm,
+    now: N,
+    timeout_us: u64,
+}
+
+#[cfg(feature = "uart_syslink")]
+impl<'a, N: FnMut() -> u64> SyslinkRoundTrip<'a, N> {
+    /// Request the `nRF51` version, waiting at most `timeout_us` microseconds for the answer
+    ///
+    /// `now` returns the current time in microseconds, see
+    /// [`UartComm::wait_for`](crate::uart_syslink::UartComm::wait_for).
+    pub fn new(comm: &'a mut UartComm, now: N, timeout_us: u64) -> Self {
+        SyslinkRoundTrip {
+            comm,
+            now,
+            timeout_us,
+        }
+    }
+}
+
+#[cfg(feature = "uart_syslink")]
+impl<N: FnMut() -> u64> SelfTest for SyslinkRoundTrip<'_, N> {
+    fn name(&self) -> &'static str {
+        "syslink"
+    }
+
+    fn run(&mut self) -> Result<(), Reason> {
+        let request = syslink::Packet::new(sys::NRF_VERSION, &[]);
+        self.comm.send(request).map_err(|_| "send failed")?;
+        self.comm
+            .wait_for(&mut self.now, self.timeout_us, |p| {
+                p.packet_type() == sys::NRF_VERSION
+            })
+            .map(|_| ())
+            .ok_or("no answer")
+    }
+}
+
+/// Check that the motor `PWM` channels accept a duty cycle, without spinning the motors
+///
+/// The motors are left disabled and stopped after the test.
+pub struct MotorPwm<'a>(pub &'a mut Motors);
+
+impl SelfTest for MotorPwm<'_> {
+    fn name(&self) -> &'static str {
+        "motors"
+    }
+
+    fn run(&mut self) -> Result<(), Reason> {
+        let check = |motor: &mut Motor| {
+            if motor.get_max_duty() == 0 {
+                return Err("no pwm period");
+            }
+            // The motors are disabled so setting a duty cycle does not spin them
+            motor.set_duty(1);
+            let duty = motor.get_duty();
+            motor.stop();
+            if duty == 1 {
+                Ok(())
+            } else {
+                Err("duty not set")
+            }
+        };
+        self.0.disable();
+        let result = check(&mut self.0.m1)
+            .and_then(|_| check(&mut self.0.m2))
+            .and_then(|_| check(&mut self.0.m3))
+            .and_then(|_| check(&mut self.0.m4));
+        self.0.stop();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test with a fixed outcome
+    struct Fixed(&'static str, Result<(), Reason>);
+
+    impl SelfTest for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn run(&mut self) -> Result<(), Reason> {
+            self.1
+        }
+    }
+
+    fn report() -> Report {
+        run(&mut [&mut Fixed("ab", Ok(())), &mut Fixed("c", Err("xyz"))])
+    }
+
+    #[test]
+    fn collects_results() {
+        let report = report();
+        assert!(!report.passed());
+        let names: std::vec::Vec<_> = report.iter().map(|r| r.name).collect();
+        assert_eq!(names, ["ab", "c"]);
+        assert_eq!(report.iter().nth(1).unwrap().outcome, Err("xyz"));
+    }
+
+    #[test]
+    fn writes_all_results() {
+        let mut buffer = [0u8; 32];
+        let len = report().write(&mut buffer);
+        assert_eq!(
+            &buffer[..len],
+            &[2, 2, b'a', b'b', 0, 1, b'c', 1, 3, b'x', b'y', b'z'][..]
+        );
+    }
+
+    #[test]
+    fn truncated_report_counts_written_results() {
+        let mut buffer = [0u8; 8];
+        let len = report().write(&mut buffer);
+        assert_eq!(&buffer[..len], &[1, 2, b'a', b'b', 0][..]);
+        assert_eq!(report().write(&mut buffer[..4]), 1);
+        assert_eq!(buffer[0], 0);
+        assert_eq!(report().write(&mut []), 0);
+    }
+
+    #[test]
+    fn stops_at_max_tests() {
+        let mut report = Report::new();
+        for _ in 0..MAX_TESTS {
+            assert!(report.run(&mut Fixed("t", Ok(()))));
+        }
+        assert!(!report.run(&mut Fixed("t", Ok(()))));
+        assert!(report.passed());
+    }
+}
@@ -0,0 +1,44 @@
+//! Connection to the on-board sensors
+//!
+//! The IMU (`BMI088`) and barometer (`BMP388`) of the Crazyflie 2.1 share the `I2C3` bus. This
+//! module sets up the bus and lists the addresses and chip IDs of the sensors, the sensor drivers
+//! themselves are not part of this crate.
+use crate::hal::gpio::{gpioa::PA8, gpioc::PC9, AlternateOD, Floating, Input, AF4};
+use crate::hal::i2c;
+use crate::hal::pac::I2C3;
+use crate::hal::prelude::*;
+use crate::hal::rcc::Clocks;
+
+/// I2C instance which is used to communicate with the on-board sensors
+pub type I2c = i2c::I2c<I2C3, (PA8<AlternateOD<AF4>>, PC9<AlternateOD<AF4>>)>;
+
+/// I2C speed in kHz
+const I2C_SPEED_KHZ: u32 = 400;
+
+/// I2C address of the `BMI088` accelerometer
+pub const BMI088_ACCEL_ADDRESS: u8 = 0x18;
+/// I2C address of the `BMI088` gyroscope
+pub const BMI088_GYRO_ADDRESS: u8 = 0x69;
+/// I2C address of the `BMP388` barometer
+pub const BMP388_ADDRESS: u8 = 0x77;
+
+/// Register holding the chip ID, the same for all on-board sensors
+pub const CHIP_ID_REGISTER: u8 = 0x00;
+/// Chip ID of the `BMI088` accelerometer
+pub const BMI088_ACCEL_CHIP_ID: u8 = 0x1E;
+/// Chip ID of the `BMI088` gyroscope
+pub const BMI088_GYRO_CHIP_ID: u8 = 0x0F;
+/// Chip ID of the `BMP388` barometer
+pub const BMP388_CHIP_ID: u8 = 0x50;
+
+/// Create a connection to the sensor bus
+pub fn new(
+    i2c3: I2C3,
+    scl_pin: PA8<Input<Floating>>,
+    sda_pin: PC9<Input<Floating>>,
+    clocks: Clocks,
+) -> I2c {
+    let scl = scl_pin.into_alternate_af4_open_drain();
+    let sda = sda_pin.into_alternate_af4_open_drain();
+    i2c::I2c::new(i2c3, (scl, sda), I2C_SPEED_KHZ.khz(), clocks)
+}
@@ -0,0 +1,352 @@
+//! Standard system status indications on the LEDs
+//!
+//! The patterns shown here follow the LED sequences of the [official
+//! firmware](https://github.com/bitcraze/crazyflie-firmware/blob/master/src/hal/src/ledseq.c) so
+//! that the state of the Crazyflie can be read the same way regardless of which firmware runs.
+//!
+//! # Usage
+//! Wrap the [`Leds`] in [`StatusLeds`], activate a [`Status`] with [`StatusLeds::set`] and call
+//! [`StatusLeds::update`] periodically with the time elapsed since the last call. Several statuses
+//! can be active at the same time, if they share a LED the status with the highest priority is
+//! shown. The variants of [`Status`] are listed in order of decreasing priority.
+
+use crate::led::{LedN, Leds, OnboardPin};
+use embedded_hal::digital::v2::OutputPin;
+
+/// A single step in a LED [`Sequence`]
+#[derive(Copy, Clone, Debug)]
+pub struct Step {
+    /// Should the LED be lit during this step
+    pub on: bool,
+    /// Duration of the step in milliseconds, must be larger than zero
+    pub duration_ms: u32,
+}
+
+/// A blinking pattern shown on a single LED
+#[derive(Copy, Clone, Debug)]
+pub struct Sequence {
+    /// The LED the pattern is shown on
+    pub led: LedN,
+    /// Steps of the pattern
+    pub steps: &'static [Step],
+    /// Restart the pattern once it is done, otherwise the status is deactivated
+    pub repeat: bool,
+}
+
+const fn step(on: bool, duration_ms: u32) -> Step {
+    Step { on, duration_ms }
+}
+
+/// LED lit continuously
+const SEQ_STEADY: &[Step] = &[step(true, 1000)];
+/// Five quick flashes
+const SEQ_TEST_PASSED: &[Step] = &[
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+];
+/// Five quick flashes followed by a pause
+const SEQ_TEST_FAILED: &[Step] = &[
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 50),
+    step(true, 50),
+    step(false, 1000),
+];
+/// 1 Hz blinking
+const SEQ_CHARGING: &[Step] = &[step(true, 200), step(false, 800)];
+/// Short flash every other second, 0.5 Hz
+const SEQ_ALIVE: &[Step] = &[step(true, 50), step(false, 1950)];
+/// 2 Hz blinking
+const SEQ_CALIBRATED: &[Step] = &[step(true, 50), step(false, 450)];
+/// Single short flash
+const SEQ_LINK: &[Step] = &[step(true, 10)];
+
+/// Standard system states of the Crazyflie
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Self-test failed, left red LED repeatedly flashes
+    SelfTestFailed,
+    /// Self-test passed, left green LED flashes five times
+    SelfTestPassed,
+    /// Low battery, right red LED is lit continuously
+    LowBattery,
+    /// Battery is fully charged, blue LED is lit continuously
+    Charged,
+    /// Battery is charging, blue LED blinks at 1 Hz
+    Charging,
+    /// Sensors are calibrating, right red LED blinks at 0.5 Hz
+    Calibrating,
+    /// Sensors are calibrated and the system is ready, right red LED blinks at 2 Hz
+    Calibrated,
+    /// System is booting, right red LED is lit continuously
+    Booting,
+    /// Radio packet received, left green LED flickers briefly
+    ///
+    /// Like the official firmware this status should be set for every received radio packet, the
+    /// LED will then flicker while the radio link is active.
+    RadioConnected,
+}
+
+/// Number of different [`Status`] values
+const NUM_STATUS: usize = 9;
+
+impl Status {
+    /// All statuses in order of decreasing priority
+    pub const ALL: [Status; NUM_STATUS] = [
+        Status::SelfTestFailed,
+        Status::SelfTestPassed,
+        Status::LowBattery,
+        Status::Charged,
+        Status::Charging,
+        Status::Calibrating,
+        Status::Calibrated,
+        Status::Booting,
+        Status::RadioConnected,
+    ];
+
+    /// The LED sequence used to show this status
+    pub fn sequence(self) -> Sequence {
+        match self {
+            Status::SelfTestFailed => Sequence {
+                led: LedN::RedLeft,
+                steps: SEQ_TEST_FAILED,
+                repeat: true,
+            },
+            Status::SelfTestPassed => Sequence {
+                led: LedN::GreenLeft,
+                steps: SEQ_TEST_PASSED,
+                repeat: false,
+            },
+            Status::LowBattery => Sequence {
+                led: LedN::RedRight,
+                steps: SEQ_STEADY,
+                repeat: true,
+            },
+            Status::Charged => Sequence {
+                led: LedN::BlueLeft,
+                steps: SEQ_STEADY,
+                repeat: true,
+            },
+            Status::Charging => Sequence {
+                led: LedN::BlueLeft,
+                steps: SEQ_CHARGING,
+                repeat: true,
+            },
+            Status::Calibrating => Sequence {
+                led: LedN::RedRight,
+                steps: SEQ_ALIVE,
+                repeat: true,
+            },
+            Status::Calibrated => Sequence {
+                led: LedN::RedRight,
+                steps: SEQ_CALIBRATED,
+                repeat: true,
+            },
+            Status::Booting => Sequence {
+                led: LedN::RedRight,
+                steps: SEQ_STEADY,
+                repeat: true,
+            },
+            Status::RadioConnected => Sequence {
+                led: LedN::GreenLeft,
+                steps: SEQ_LINK,
+                repeat: false,
+            },
+        }
+    }
+}
+
+/// Progress of the sequence of a single status
+#[derive(Copy, Clone, Debug, Default)]
+struct SequenceState {
+    active: bool,
+    step: usize,
+    elapsed_ms: u32,
+}
+
+impl SequenceState {
+    /// Advance the sequence by `elapsed_ms`
+    fn advance(&mut self, sequence: &Sequence, elapsed_ms: u32) {
+        self.elapsed_ms = self.elapsed_ms.saturating_add(elapsed_ms);
+        while self.active && self.elapsed_ms >= sequence.steps[self.step].duration_ms {
+            self.elapsed_ms -= sequence.steps[self.step].duration_ms;
+            self.step += 1;
+            if self.step == sequence.steps.len() {
+                self.step = 0;
+                self.active = sequence.repeat;
+            }
+        }
+    }
+}
+
+/// Status indication layer on top of [`Leds`]
+pub struct StatusLeds<P = OnboardPin> {
+    leds: Leds<P>,
+    states: [SequenceState; NUM_STATUS],
+}
+
+impl<P: OutputPin> StatusLeds<P> {
+    /// Create a new status indicator, all LEDs are turned off
+    pub fn new(mut leds: Leds<P>) -> Result<Self, P::Error> {
+        leds.clear_all()?;
+        Ok(StatusLeds {
+            leds,
+            states: [SequenceState::default(); NUM_STATUS],
+        })
+    }
+
+    /// Activate a status, restarting its sequence if it was already active
+    pub fn set(&mut self, status: Status) {
+        self.states[status as usize] = SequenceState {
+            active: true,
+            step: 0,
+            elapsed_ms: 0,
+        };
+    }
+
+    /// Deactivate a status
+    pub fn clear(&mut self, status: Status) {
+        self.states[status as usize].active = false;
+    }
+
+    /// Deactivate all statuses
+    pub fn clear_all(&mut self) {
+        self.states.iter_mut().for_each(|s| s.active = false);
+    }
+
+    /// Check if a status is currently active
+    ///
+    /// Statuses with non-repeating sequences are deactivated automatically once done.
+    pub fn is_active(&self, status: Status) -> bool {
+        self.states[status as usize].active
+    }
+
+    /// Advance all active sequences by `elapsed_ms` milliseconds and update the LEDs
+    pub fn update(&mut self, elapsed_ms: u32) -> Result<(), P::Error> {
+        for (state, status) in self.states.iter_mut().zip(Status::ALL.iter()) {
+            state.advance(&status.sequence(), elapsed_ms);
+        }
+        for led in &[
+            LedN::RedLeft,
+            LedN::GreenLeft,
+            LedN::BlueLeft,
+            LedN::RedRight,
+            LedN::GreenRight,
+        ] {
+            // The first active status is the one with highest priority
+            let on = self
+                .states
+                .iter()
+                .zip(Status::ALL.iter())
+                .map(|(state, status)| (state, status.sequence()))
+                .find(|(state, seq)| state.active && seq.led == *led)
+                .map_or(false, |(state, seq)| seq.steps[state.step].on);
+            self.leds[*led].set(on)?;
+        }
+        Ok(())
+    }
+
+    /// Release the underlying LEDs
+    pub fn free(self) -> Leds<P> {
+        self.leds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::{Led, Polarity};
+    use embedded_hal_mock::pin::{Mock, State, Transaction};
+
+    /// LED expecting to be turned off on creation and then set to `states`, one per update
+    fn led(states: &[bool]) -> Led<Mock> {
+        let transactions: std::vec::Vec<_> = std::iter::once(false)
+            .chain(states.iter().copied())
+            .map(|on| Transaction::set(if on { State::High } else { State::Low }))
+            .collect();
+        Led::new(Mock::new(&transactions), Polarity::ActiveHigh)
+    }
+
+    /// Status LEDs expecting the states of each LED, in the order of [`LedN`]
+    fn status_leds(states: [&[bool]; 5]) -> StatusLeds<Mock> {
+        let leds = Leds::from_leds([
+            led(states[0]),
+            led(states[1]),
+            led(states[2]),
+            led(states[3]),
+            led(states[4]),
+        ]);
+        StatusLeds::new(leds).unwrap()
+    }
+
+    /// Check that all expected LED states were set
+    fn done(leds: StatusLeds<Mock>) {
+        let [a, b, c, d, e] = leds.free().free();
+        for pin in &mut [a.free(), b.free(), c.free(), d.free(), e.free()] {
+            pin.done();
+        }
+    }
+
+    fn update(leds: &mut StatusLeds<Mock>, elapsed_ms: &[u32]) {
+        for &elapsed in elapsed_ms {
+            leds.update(elapsed).unwrap();
+        }
+    }
+
+    #[test]
+    fn calibrated_blinks_at_2_hz() {
+        let off = &[false; 5];
+        let mut leds = status_leds([off, off, off, &[true, true, false, false, true], off]);
+        leds.set(Status::Calibrated);
+        update(&mut leds, &[0, 49, 1, 449, 1]);
+        done(leds);
+    }
+
+    #[test]
+    fn calibrating_blinks_at_half_hz() {
+        let off = &[false; 5];
+        let mut leds = status_leds([off, off, off, &[true, true, false, false, true], off]);
+        leds.set(Status::Calibrating);
+        update(&mut leds, &[0, 49, 1, 1949, 1]);
+        done(leds);
+    }
+
+    #[test]
+    fn higher_priority_status_is_shown() {
+        let off = &[false; 3];
+        let mut leds = status_leds([off, off, off, &[true, true, false], off]);
+        leds.set(Status::Calibrating);
+        leds.set(Status::LowBattery);
+        // Low battery keeps the LED lit while calibrating would have turned it off
+        update(&mut leds, &[0, 100]);
+        leds.clear(Status::LowBattery);
+        update(&mut leds, &[0]);
+        assert!(leds.is_active(Status::Calibrating));
+        done(leds);
+    }
+
+    #[test]
+    fn non_repeating_status_turns_off() {
+        let off = &[false; 4];
+        let mut leds = status_leds([off, &[true, true, false, false], off, off, off]);
+        leds.set(Status::SelfTestPassed);
+        update(&mut leds, &[0, 49, 1]);
+        assert!(leds.is_active(Status::SelfTestPassed));
+        update(&mut leds, &[450]);
+        assert!(!leds.is_active(Status::SelfTestPassed));
+        done(leds);
+    }
+}
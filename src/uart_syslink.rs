@@ -1,33 +1,58 @@
 //! Communication support between the `STM32F405` and `nRF51`
 use crate::hal::gpio::{
+    gpioa::PA4,
     gpioc::{PC6, PC7},
-    Alternate, Floating, Input, AF8,
+    Alternate, Floating, Input, PullDown, AF8,
 };
 use crate::hal::nb;
-use crate::hal::pac::USART6;
+use crate::hal::pac::{EXTI, SYSCFG, USART6};
 use crate::hal::prelude::*;
 use crate::hal::rcc::Clocks;
 use crate::hal::serial::{self, config, Serial};
-use heapless::{consts::U70, Vec};
+use core::convert::Infallible;
+use embedded_hal::digital::v2::InputPin;
+use embedded_hal::serial::{Read, Write};
+use parser::Parser;
+pub use stats::LinkStats;
 use syslink;
 
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod dispatch;
+pub mod dma;
+#[cfg(test)]
+mod loopback;
+pub mod message;
+mod parser;
+pub mod radio;
+mod stats;
+
 pub type TxPin = PC6<Alternate<AF8>>;
 pub type RxPin = PC7<Alternate<AF8>>;
 /// Underlying UART connection
 pub type SerialConn = Serial<USART6, (TxPin, RxPin)>;
+/// Flow control pin of the `nRF51`, high while it can not accept more data
+pub type FlowControlPin = PA4<Input<PullDown>>;
 
 const BAUDRATE: u32 = 1_000_000;
+/// `EXTI` line of the flow control pin
+const FLOW_CONTROL_EXTI_LINE: u32 = 4;
+/// Number of times the flow control pin is polled before giving up on a busy `nRF51`, this is a
+/// few milliseconds at 168 MHz
+const MAX_BUSY_POLLS: u32 = 100_000;
 
 /// Potential errors that could occur when sending [`syslink`] packets
-pub enum SendError {
+pub enum SendError<E = serial::Error> {
     /// Error related to writing a [`syslink`] packet to a byte stream
     Syslink(syslink::WriteError),
     /// Problem sending or receiving data on the underlying UART connection
-    Uart(serial::Error),
+    Uart(E),
+    /// The receiver stayed busy for too long, the packet was possibly only partially sent
+    Busy,
 }
 
 /// Potential errors that could occur when receiving a [`syslink`] packet
-pub enum RecvError {
+pub enum RecvError<E = serial::Error> {
     /// Error related to parsing the raw stream of bytes into a valid packet
     ///
     /// For the errors that signal truncation needed this will be handled by this module and is not
@@ -35,16 +60,38 @@ pub enum RecvError {
     /// the user.
     Syslink(syslink::ParseError),
     /// Problem receiving data on the underlying UART connection
-    Uart(serial::Error),
+    Uart(E),
     /// The receive buffer has filled up
     ReceiveBufferFull,
 }
 
+/// Flow control for transports where the receiver is always ready
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoFlowControl;
+
+impl InputPin for NoFlowControl {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
 /// Internal communication channel between `STM32F405` and `nRF51` based on reading the
 /// corresponding UART channel
-pub struct UartComm {
-    conn: SerialConn,
-    recv_buff: Vec<u8, U70>,
+///
+/// The syslink framing is independent of the serial transport `S`, any
+/// [`Read`](embedded_hal::serial::Read) and [`Write`](embedded_hal::serial::Write) implementation
+/// can be used, e.g. an in-memory loopback when testing on the host. Transmission is paused while
+/// the flow control pin `F` is high, use [`NoFlowControl`] for transports without flow control.
+pub struct UartComm<S = SerialConn, F = FlowControlPin> {
+    conn: S,
+    flow: F,
+    parser: Parser,
 }
 
 impl UartComm {
@@ -53,6 +100,7 @@ impl UartComm {
         usart: USART6,
         tx_pin: PC6<Input<Floating>>,
         rx_pin: PC7<Input<Floating>>,
+        flow_pin: PA4<Input<Floating>>,
         clocks: Clocks,
     ) -> Self {
         let tx_pin = tx_pin.into_alternate_af8();
@@ -62,90 +110,237 @@ impl UartComm {
             .parity_none()
             .wordlength_8()
             .stopbits(config::StopBits::STOP1);
-        Self {
-            // Unwrap safety: Since the configuration is controlled internally it should always be
-            // correct, if this fails then the method will have to be reconfigured
-            conn: Serial::usart6(usart, (tx_pin, rx_pin), uart_cfg, clocks).unwrap(),
-            recv_buff: Vec::new(),
+        // Unwrap safety: Since the configuration is controlled internally it should always be
+        // correct, if this fails then the method will have to be reconfigured
+        let conn = Serial::usart6(usart, (tx_pin, rx_pin), uart_cfg, clocks).unwrap();
+        // Pull down so that a missing `nRF51` does not block transmission forever
+        UartComm::from_serial(conn, flow_pin.into_pull_down_input())
+    }
+
+    /// Enable interrupt ([`Event`](serial::Event)) for the UART connection
+    pub fn enable_interrupt(&mut self, event: serial::Event) {
+        self.conn.listen(event);
+    }
+
+    /// Disable interrupt ([`Event`](serial::Event)) for the UART connection
+    pub fn disable_interrupt(&mut self, event: serial::Event) {
+        self.conn.unlisten(event);
+    }
+
+    /// Trigger [`SYSLINK_FLOW_CONTROL`](crate::irq::SYSLINK_FLOW_CONTROL) when the `nRF51` becomes
+    /// busy or ready again
+    pub fn enable_flow_control_interrupt(&mut self) {
+        cortex_m::interrupt::free(|_| {
+            // Safety: Only the bits of the flow control line are changed inside a critical
+            // section, the SYSCFG clock is enabled when the board is initialized
+            let syscfg = unsafe { &*SYSCFG::ptr() };
+            let exti = unsafe { &*EXTI::ptr() };
+            // Port A is selected by clearing the line in the external interrupt configuration
+            syscfg.exticr2.modify(|r, w| unsafe { w.bits(r.bits() & !0xF) });
+            let line = 1 << FLOW_CONTROL_EXTI_LINE;
+            exti.rtsr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+            exti.ftsr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+            exti.imr.modify(|r, w| unsafe { w.bits(r.bits() | line) });
+        });
+    }
+}
+
+/// Clear the pending flow control interrupt, returning `true` if it was pending
+pub(crate) fn clear_flow_control_interrupt() -> bool {
+    // Safety: Writing the pending register only clears the bit of the flow control line
+    let exti = unsafe { &*EXTI::ptr() };
+    let line = 1 << FLOW_CONTROL_EXTI_LINE;
+    let pending = exti.pr.read().bits() & line != 0;
+    if pending {
+        #[allow(unused_unsafe)]
+        exti.pr.write(|w| unsafe { w.bits(line) });
+    }
+    pending
+}
+
+impl<S, F> UartComm<S, F> {
+    /// Create a communication channel on top of an already configured serial connection
+    pub fn from_serial(conn: S, flow: F) -> Self {
+        UartComm {
+            conn,
+            flow,
+            parser: Parser::new(),
         }
     }
 
+    /// Empty receiver buffer
+    ///
+    /// This might be necessary if the receiver buffer fills up to max capacity.
+    pub fn empty_buffer(&mut self) {
+        self.parser.clear();
+    }
+
+    /// Snapshot of the link statistics
+    pub fn stats(&self) -> LinkStats {
+        self.parser.stats
+    }
+
+    /// Reset all link statistics to zero
+    pub fn reset_stats(&mut self) {
+        self.parser.stats = LinkStats::default();
+    }
+
+    /// Release the underlying serial connection and flow control pin
+    pub fn free(self) -> (S, F) {
+        (self.conn, self.flow)
+    }
+}
+
+impl<S, F: InputPin<Error = Infallible>> UartComm<S, F> {
+    /// Check if the receiver can not accept more data
+    pub fn is_busy(&self) -> bool {
+        // Unwrap safety: Reading the flow control pin can not fail
+        self.flow.is_high().unwrap()
+    }
+}
+
+impl<S, F, RE, WE> UartComm<S, F>
+where
+    S: Read<u8, Error = RE> + Write<u8, Error = WE>,
+    F: InputPin<Error = Infallible>,
+{
     /// Send a [`Packet`](syslink::Packet) over UART to the `nRF51`, blocking to write whole packet
-    pub fn send(&mut self, packet: syslink::Packet) -> Result<(), SendError> {
+    ///
+    /// Transmission is paused while the `nRF51` signals that it is busy, if it stays busy
+    /// [`SendError::Busy`] is returned instead of waiting forever.
+    pub fn send(&mut self, packet: syslink::Packet) -> Result<(), SendError<WE>> {
         let mut buffer = [0u8; 72];
         let bytes = packet.write(&mut buffer).map_err(SendError::Syslink)?;
-        self.conn
-            .bwrite_all(&buffer[..bytes])
-            .map_err(SendError::Uart)?;
+        for byte in &buffer[..bytes] {
+            let mut polls = 0;
+            while self.is_busy() {
+                polls += 1;
+                if polls == MAX_BUSY_POLLS {
+                    return Err(SendError::Busy);
+                }
+            }
+            nb::block!(self.conn.write(*byte)).map_err(SendError::Uart)?;
+        }
+        self.parser.stats.sent = self.parser.stats.sent.wrapping_add(1);
         Ok(())
     }
 
     /// Receive a new [`Packet`](syslink::Packet) over UART from the `nRF51`
-    pub fn receive(&mut self) -> nb::Result<syslink::Packet, RecvError> {
+    pub fn receive(&mut self) -> nb::Result<syslink::Packet, RecvError<RE>> {
         // Try to read from UART connection
         let data = self.conn.read().map_err(|e| e.map(RecvError::Uart))?;
-        // Try to add to our receive buffer, overflow here should "never" happen, but if it does we
-        // signal it so that calling users can clear the buffer
-        self.recv_buff.push(data).map_err(|_| RecvError::ReceiveBufferFull)?;
-        // Try to parse packet from data buffer
-        match syslink::Packet::from(&self.recv_buff) {
-            // If we parsed a complete packet, clear the internal buffer and return the packet
-            Ok((slice, packet)) => {
-                // Unwrap safety: Since the slice already comes from 'recv_buff' this can never
-                // fail
-                self.recv_buff = Vec::from_slice(&slice).unwrap();
-                Ok(packet)
-            }
-            // Not yet done parsing, do nothing with the internal buffer and signal blockage
-            Err(syslink::ParseError::Incomplete(_)) => Err(nb::Error::WouldBlock),
-            // The other parsing errors require us to truncate the underlying receive buffer before
-            // returning the error
-            Err(e @ syslink::ParseError::WrongTag) => {
-                self.truncate_first(1);
-                Err(nb::Error::Other(RecvError::Syslink(e)))
-            }
-            Err(syslink::ParseError::TooMuchData(tr)) => {
-                self.truncate_first(tr);
-                Err(nb::Error::Other(RecvError::Syslink(
-                    syslink::ParseError::TooMuchData(tr),
-                )))
-            }
-            Err(syslink::ParseError::WrongChecksum(tr)) => {
-                self.truncate_first(tr);
-                Err(nb::Error::Other(RecvError::Syslink(
-                    syslink::ParseError::WrongChecksum(tr),
-                )))
+        self.parser.push(data)?;
+        self.parser.parse()
+    }
+
+    /// Wait at most `timeout_us` microseconds for a packet accepted by `is_answer`
+    ///
+    /// `now` returns the current time in microseconds, e.g. `|| mono.now()` with
+    /// [`MonoTimer`](crate::monotonic::MonoTimer) or the `now` of the monotonic of an RTIC
+    /// application. Other packets received while waiting are discarded and counted in
+    /// [`LinkStats::dropped`]. Returns `None` if no answer was received in time.
+    pub fn wait_for(
+        &mut self,
+        mut now: impl FnMut() -> u64,
+        timeout_us: u64,
+        mut is_answer: impl FnMut(&syslink::Packet) -> bool,
+    ) -> Option<syslink::Packet> {
+        let start = now();
+        while now().wrapping_sub(start) < timeout_us {
+            if let Ok(packet) = self.receive() {
+                if is_answer(&packet) {
+                    return Some(packet);
+                }
+                self.parser.stats.dropped = self.parser.stats.dropped.wrapping_add(1);
             }
         }
+        None
     }
+}
 
-    /// Remove the first `bytes` from the internal receiver buffer
-    ///
-    /// This method is used when a parse error occurs and we need to discard a given number of
-    /// bytes from the start of the buffer.
-    fn truncate_first(&mut self, bytes: usize) {
-        for _ in 0..bytes {
-            // Exit early if we are asked to remove more values than are stored in the buffer
-            if let None = self.recv_buff.pop() {
-                break;
+#[cfg(test)]
+mod tests {
+    use super::loopback::{clock, Loopback};
+    use super::*;
+
+    /// Poll for a packet until the loopback is drained
+    fn receive(comm: &mut UartComm<Loopback, NoFlowControl>) -> Option<syslink::Packet> {
+        while !comm.conn.rx.is_empty() {
+            if let Ok(packet) = comm.receive() {
+                return Some(packet);
             }
         }
+        None
     }
 
-    /// Empty receiver buffer
-    ///
-    /// This might be necessary if the receiver buffer fills up to max capacity.
-    pub fn empty_buffer(&mut self) {
-        self.recv_buff.clear();
+    #[test]
+    fn round_trips_packet() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        let data = [0x10, 0x20, 0x30, 0x40];
+        let packet = syslink::Packet::new(message::radio::RAW, &data);
+        assert!(comm.send(packet).is_ok());
+        let packet = receive(&mut comm).expect("packet was not received");
+        assert_eq!(packet.packet_type(), message::radio::RAW);
+        assert_eq!(packet.data(), &data[..]);
+        assert!(receive(&mut comm).is_none());
+        let stats = comm.stats();
+        assert_eq!((stats.sent, stats.received, stats.errors()), (1, 1, 0));
     }
 
-    /// Enable interrupt ([`Event`](serial::Event)) for the UART connection
-    pub fn enable_interrupt(&mut self, event: serial::Event) {
-        self.conn.listen(event);
+    #[test]
+    fn round_trips_packets_back_to_back() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        for channel in 0..4 {
+            let packet = message::RadioSetting::Channel(channel).packet();
+            assert!(comm.send(packet).is_ok());
+        }
+        for channel in 0..4 {
+            let packet = receive(&mut comm).expect("packet was not received");
+            assert_eq!(packet.packet_type(), message::radio::CHANNEL);
+            assert_eq!(packet.data(), &[channel][..]);
+        }
     }
 
-    /// Disable interrupt ([`Event`](serial::Event)) for the UART connection
-    pub fn disable_interrupt(&mut self, event: serial::Event) {
-        self.conn.unlisten(event);
+    /// Flow control of a receiver which never accepts data
+    struct AlwaysBusy;
+
+    impl InputPin for AlwaysBusy {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn send_gives_up_on_busy_receiver() {
+        let mut comm = UartComm::from_serial(Loopback::new(), AlwaysBusy);
+        let packet = syslink::Packet::new(message::radio::RAW, &[1]);
+        assert!(matches!(comm.send(packet), Err(SendError::Busy)));
+        assert!(comm.conn.rx.is_empty());
+        assert_eq!(comm.stats().sent, 0);
+    }
+
+    #[test]
+    fn wait_for_drops_other_packets() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        let raw = syslink::Packet::new(message::radio::RAW, &[1]);
+        let rssi = syslink::Packet::new(message::radio::RSSI, &[40]);
+        comm.conn.inject(raw);
+        comm.conn.inject(rssi);
+        let is_rssi = |p: &syslink::Packet| p.packet_type() == message::radio::RSSI;
+        let answer = comm.wait_for(clock(1), 1_000, is_rssi);
+        assert_eq!(answer.map(|p| p.data()[0]), Some(40));
+        assert_eq!(comm.stats().dropped, 1);
+    }
+
+    #[test]
+    fn wait_for_times_out() {
+        let mut comm = UartComm::from_serial(Loopback::silent(), NoFlowControl);
+        assert!(comm.wait_for(clock(100), 1_000, |_| true).is_none());
     }
 }
@@ -0,0 +1,226 @@
+//! Async interface to the `nRF51` communication channel
+//!
+//! [`AsyncUartComm`] wraps [`UartComm`] so that sending and receiving can be awaited from an
+//! async executor (e.g. Embassy) instead of spinning on [`nb::Error::WouldBlock`]. The tasks are
+//! woken from the `USART6` interrupt.
+//!
+//! [`AsyncDmaUartComm`] does the same for [`DmaUartComm`], its tasks are woken from the DMA,
+//! idle-line and flow control interrupts. The interrupt handlers only mask the interrupts and wake
+//! the waiting task, which then handles the interrupt flags through
+//! [`DmaUartComm::on_interrupt`]. Queued packets are therefore only sent while a task is awaiting
+//! [`AsyncDmaUartComm::receive`] or [`AsyncDmaUartComm::send`].
+//!
+//! # Usage
+//! Call [`on_interrupt`] from the `USART6` interrupt handler and [`on_flow_control_interrupt`]
+//! from the [`SYSLINK_FLOW_CONTROL`](crate::irq::SYSLINK_FLOW_CONTROL) interrupt handler, and
+//! unmask both interrupts in the `NVIC`.
+//!
+//! With DMA call [`on_dma_interrupt`] from the [`SYSLINK`](irq::SYSLINK),
+//! [`SYSLINK_DMA_RX`](irq::SYSLINK_DMA_RX), [`SYSLINK_DMA_TX`](irq::SYSLINK_DMA_TX) and
+//! [`SYSLINK_FLOW_CONTROL`](irq::SYSLINK_FLOW_CONTROL) interrupt handlers instead, these are
+//! unmasked by [`AsyncDmaUartComm`].
+use super::dma::DmaUartComm;
+use super::{RecvError, SendError, UartComm};
+use crate::hal::nb;
+use crate::hal::pac::{Interrupt, USART6};
+use crate::hal::prelude::*;
+use crate::hal::serial;
+use crate::irq;
+use crate::waker::WakerSlot;
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+use cortex_m::peripheral::NVIC;
+
+/// Task waiting to receive data
+static RX_WAKER: WakerSlot = WakerSlot::new();
+/// Task waiting to send data
+static TX_WAKER: WakerSlot = WakerSlot::new();
+/// Task waiting to send or receive through DMA
+static DMA_WAKER: WakerSlot = WakerSlot::new();
+
+/// Interrupts handled by [`DmaUartComm::on_interrupt`]
+const DMA_INTERRUPTS: [Interrupt; 4] = [
+    irq::SYSLINK,
+    irq::SYSLINK_DMA_RX,
+    irq::SYSLINK_DMA_TX,
+    irq::SYSLINK_FLOW_CONTROL,
+];
+
+/// Handle the `USART6` interrupt, waking tasks waiting to send or receive
+///
+/// The interrupt sources are disabled until the woken task needs to wait again.
+pub fn on_interrupt() {
+    // Safety: Only the interrupt enable bits are modified, these are owned by the waiting tasks
+    let usart = unsafe { &*USART6::ptr() };
+    let sr = usart.sr.read();
+    let cr1 = usart.cr1.read();
+    if cr1.rxneie().bit_is_set() && (sr.rxne().bit_is_set() || sr.ore().bit_is_set()) {
+        usart.cr1.modify(|_, w| w.rxneie().clear_bit());
+        RX_WAKER.wake();
+    }
+    if cr1.txeie().bit_is_set() && sr.txe().bit_is_set() {
+        usart.cr1.modify(|_, w| w.txeie().clear_bit());
+        TX_WAKER.wake();
+    }
+}
+
+/// Handle the flow control interrupt, waking the task waiting for the `nRF51` to accept data
+pub fn on_flow_control_interrupt() {
+    if super::clear_flow_control_interrupt() {
+        TX_WAKER.wake();
+    }
+}
+
+/// Handle the interrupts of [`DmaUartComm`], waking the task waiting to send or receive
+///
+/// The interrupts are masked in the `NVIC` until the woken task has handled them.
+pub fn on_dma_interrupt() {
+    for &interrupt in DMA_INTERRUPTS.iter() {
+        NVIC::mask(interrupt);
+    }
+    DMA_WAKER.wake();
+}
+
+/// Async communication channel between `STM32F405` and `nRF51`
+pub struct AsyncUartComm {
+    comm: UartComm,
+}
+
+impl AsyncUartComm {
+    /// Wrap a communication channel for async use
+    pub fn new(mut comm: UartComm) -> Self {
+        comm.disable_interrupt(serial::Event::Rxne);
+        comm.disable_interrupt(serial::Event::Txe);
+        comm.enable_flow_control_interrupt();
+        AsyncUartComm { comm }
+    }
+
+    /// Send a [`Packet`](syslink::Packet) to the `nRF51`, waiting while the UART or the `nRF51`
+    /// is busy
+    pub async fn send(&mut self, packet: syslink::Packet) -> Result<(), SendError> {
+        let mut buffer = [0u8; 72];
+        let bytes = packet.write(&mut buffer).map_err(SendError::Syslink)?;
+        for byte in &buffer[..bytes] {
+            let comm = &mut self.comm;
+            poll_fn(|cx| {
+                if !comm.is_busy() {
+                    return Poll::Ready(());
+                }
+                TX_WAKER.register(cx.waker());
+                // The flow control line could have changed before the waker was registered
+                if comm.is_busy() {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(())
+                }
+            })
+            .await;
+            poll_fn(|cx| match comm.conn.write(*byte) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(SendError::Uart(e))),
+                Err(nb::Error::WouldBlock) => {
+                    TX_WAKER.register(cx.waker());
+                    comm.enable_interrupt(serial::Event::Txe);
+                    Poll::Pending
+                }
+            })
+            .await?;
+        }
+        let stats = &mut self.comm.parser.stats;
+        stats.sent = stats.sent.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Receive the next [`Packet`](syslink::Packet) from the `nRF51`
+    pub async fn receive(&mut self) -> Result<syslink::Packet, RecvError> {
+        let comm = &mut self.comm;
+        poll_fn(|cx| match comm.receive() {
+            Ok(packet) => Poll::Ready(Ok(packet)),
+            Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+            Err(nb::Error::WouldBlock) => {
+                RX_WAKER.register(cx.waker());
+                comm.enable_interrupt(serial::Event::Rxne);
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Release the underlying communication channel
+    pub fn free(self) -> UartComm {
+        self.comm
+    }
+}
+
+/// Async communication channel between `STM32F405` and `nRF51` sending and receiving through DMA
+pub struct AsyncDmaUartComm {
+    comm: DmaUartComm,
+}
+
+impl AsyncDmaUartComm {
+    /// Wrap a DMA communication channel for async use and unmask its interrupts
+    pub fn new(comm: DmaUartComm) -> Self {
+        unmask_dma_interrupts();
+        AsyncDmaUartComm { comm }
+    }
+
+    /// Queue a [`Packet`](syslink::Packet) to be sent to the `nRF51`, waiting while the queue is
+    /// full
+    pub async fn send(&mut self, packet: syslink::Packet) {
+        let comm = &mut self.comm;
+        let mut packet = Some(packet);
+        poll_fn(|cx| {
+            handle_interrupts(comm, cx);
+            // Unwrap safety: The packet is put back whenever the future is not ready
+            match comm.try_send(packet.take().unwrap()) {
+                Ok(()) => Poll::Ready(()),
+                Err(full) => {
+                    packet = Some(full);
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Receive the next [`Packet`](syslink::Packet) from the `nRF51`
+    pub async fn receive(&mut self) -> Result<syslink::Packet, RecvError> {
+        let comm = &mut self.comm;
+        poll_fn(|cx| {
+            handle_interrupts(comm, cx);
+            match comm.receive() {
+                Ok(packet) => Poll::Ready(Ok(packet)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Release the underlying communication channel, leaving its interrupts masked
+    pub fn free(self) -> DmaUartComm {
+        for &interrupt in DMA_INTERRUPTS.iter() {
+            NVIC::mask(interrupt);
+        }
+        self.comm
+    }
+}
+
+/// Handle the interrupt flags of `comm` and wake the task on the next interrupt
+///
+/// Interrupts which occur while masked stay pending and are taken as soon as they are unmasked,
+/// so no wake-up is lost.
+fn handle_interrupts(comm: &mut DmaUartComm, cx: &mut Context<'_>) {
+    DMA_WAKER.register(cx.waker());
+    comm.on_interrupt();
+    unmask_dma_interrupts();
+}
+
+/// Unmask the interrupts of [`DmaUartComm`] in the `NVIC`
+fn unmask_dma_interrupts() {
+    for &interrupt in DMA_INTERRUPTS.iter() {
+        // Safety: The handlers only mask the interrupts again and wake the waiting task, they do
+        // not access any state shared with the task
+        unsafe { NVIC::unmask(interrupt) };
+    }
+}
@@ -0,0 +1,102 @@
+//! Routing of received [`syslink`] packets to handlers
+//!
+//! # Usage
+//! Implement [`Handler`] for each part of the application interested in syslink messages,
+//! overriding only the methods of the groups it cares about. Pass every received packet to
+//! [`dispatch`] together with the handlers, the packet is decoded once and every handler is called
+//! in the order given. The handlers are only borrowed for the call, so their state can be read
+//! between packets.
+use super::message::{DecodeError, Message, OneWire, Power, Radio, System};
+
+/// Receiver of decoded syslink messages
+///
+/// All methods do nothing by default.
+pub trait Handler {
+    /// Handle a message of the radio group
+    fn radio(&mut self, _message: Radio<'_>) {}
+
+    /// Handle a message of the power management group
+    fn power(&mut self, _message: Power) {}
+
+    /// Handle a message of the one-wire memory group
+    fn one_wire(&mut self, _message: OneWire<'_>) {}
+
+    /// Handle a message of the system group
+    fn system(&mut self, _message: System<'_>) {}
+
+    /// Handle a packet of an unknown type
+    fn unknown(&mut self, _packet_type: u8, _data: &[u8]) {}
+}
+
+/// Decode a packet and pass it to all handlers
+pub fn dispatch(
+    packet: &syslink::Packet,
+    handlers: &mut [&mut dyn Handler],
+) -> Result<(), DecodeError> {
+    let message = Message::decode(packet)?;
+    for handler in handlers.iter_mut() {
+        match message {
+            Message::Radio(m) => handler.radio(m),
+            Message::Power(m) => handler.power(m),
+            Message::OneWire(m) => handler.one_wire(m),
+            Message::System(m) => handler.system(m),
+            Message::Unknown { packet_type, data } => handler.unknown(packet_type, data),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart_syslink::message::{pm, radio};
+
+    /// Counts the messages of each group
+    #[derive(Default)]
+    struct Counter {
+        radio: usize,
+        power: usize,
+        unknown: usize,
+    }
+
+    impl Handler for Counter {
+        fn radio(&mut self, _message: Radio<'_>) {
+            self.radio += 1;
+        }
+
+        fn power(&mut self, _message: Power) {
+            self.power += 1;
+        }
+
+        fn unknown(&mut self, _packet_type: u8, _data: &[u8]) {
+            self.unknown += 1;
+        }
+    }
+
+    #[test]
+    fn calls_every_handler() {
+        let mut first = Counter::default();
+        let mut second = Counter::default();
+        let packets = [
+            syslink::Packet::new(radio::RAW, &[1]),
+            syslink::Packet::new(pm::ONOFF_SWITCHOFF, &[]),
+            syslink::Packet::new(0x40, &[]),
+        ];
+        for packet in packets.iter() {
+            assert_eq!(dispatch(packet, &mut [&mut first, &mut second]), Ok(()));
+            // The handlers can be read between packets
+            assert_eq!(first.radio, second.radio);
+        }
+        for counter in [first, second].iter() {
+            assert_eq!((counter.radio, counter.power, counter.unknown), (1, 1, 1));
+        }
+    }
+
+    #[test]
+    fn decode_error_skips_handlers() {
+        let mut counter = Counter::default();
+        let packet = syslink::Packet::new(radio::RSSI, &[]);
+        assert!(dispatch(&packet, &mut [&mut counter]).is_err());
+        assert_eq!(counter.radio, 0);
+    }
+}
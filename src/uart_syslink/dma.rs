@@ -0,0 +1,427 @@
+//! DMA based communication channel to the `nRF51`
+//!
+//! Instead of an interrupt for every received byte, `USART6` writes received bytes into a circular
+//! buffer through `DMA2` stream 1 (channel 5). The idle-line interrupt of `USART6`, raised when
+//! the `nRF51` stops sending, together with the half and full transfer interrupts of the stream
+//! signal that new data is available. The buffered bytes are then handed to the same syslink
+//! parser as used by [`UartComm`], producing the same [`Packet`](syslink::Packet)s.
+//!
+//! Packets to send are put in a bounded queue with [`DmaUartComm::try_send`], which never blocks.
+//! The queue is drained by `DMA2` stream 6 (channel 5), one packet per transfer. The transfer is
+//! paused while the `nRF51` signals through its flow control pin that it is busy, and resumed
+//! where it stopped once the `nRF51` is ready again.
+//!
+//! # Usage
+//! Create [`DmaUartComm`] from the [`UartComm`] and `DMA2` of the
+//! [`Board`](crate::board::Board), together with [`Buffers`] which are not placed in CCM RAM (see
+//! [`ccmram`](crate::ccmram)). Call [`DmaUartComm::on_interrupt`] from the
+//! [`SYSLINK`](crate::irq::SYSLINK), [`SYSLINK_DMA_RX`](crate::irq::SYSLINK_DMA_RX),
+//! [`SYSLINK_DMA_TX`](crate::irq::SYSLINK_DMA_TX) and
+//! [`SYSLINK_FLOW_CONTROL`](crate::irq::SYSLINK_FLOW_CONTROL) interrupts and then
+//! [`DmaUartComm::receive`] until it returns [`WouldBlock`](nb::Error::WouldBlock).
+//!
+//! At 1 Mbaud the buffer fills in roughly 2.5 ms, received data must be processed faster than that
+//! to not be overwritten. Overwritten data is detected from the half and full transfer interrupts,
+//! discarded and counted in [`LinkStats::rx_overruns`].
+use super::parser::Parser;
+use super::{clear_flow_control_interrupt, LinkStats, RecvError, UartComm};
+use crate::ccmram;
+use crate::hal::nb;
+use crate::hal::pac::{DMA2, RCC, USART6};
+use crate::hal::serial;
+use heapless::{consts::U8, spsc::Queue};
+
+/// Size of the circular receive buffer in bytes
+pub const RX_BUFFER_LEN: usize = 256;
+/// Half of the receive buffer, DMA raises an interrupt each time it fills one half
+const RX_HALF_LEN: usize = RX_BUFFER_LEN / 2;
+/// Size of the transmit buffer in bytes, large enough for the largest syslink packet
+pub const TX_BUFFER_LEN: usize = 72;
+/// Maximum number of packets waiting to be sent
+pub type TxQueueLen = U8;
+
+/// Stream of `DMA2` connected to `USART6_RX`
+const RX_STREAM: usize = 1;
+/// Stream of `DMA2` connected to `USART6_TX`
+const TX_STREAM: usize = 6;
+/// Channel of `USART6` on the `DMA2` streams
+const USART6_CHANNEL: u32 = 5;
+/// All interrupt flags of stream 1 in the `LISR` and `LIFCR` registers
+const RX_STREAM_FLAGS: u32 = 0b11_1101 << 6;
+/// All interrupt flags of stream 6 in the `HISR` and `HIFCR` registers
+const TX_STREAM_FLAGS: u32 = 0b11_1101 << 16;
+/// Half and full transfer flags of stream 1 in `LISR`
+const RX_HALF_FLAGS: [u32; 2] = [1 << 10, 1 << 11];
+/// Transfer and direct mode error flags of stream 1 in `LISR`
+const RX_ERROR_FLAGS: u32 = 0b11 << 8;
+/// Transfer and direct mode error flags of stream 6 in `HISR`
+const TX_ERROR_FLAGS: u32 = 0b11 << 18;
+
+// Bits of the stream configuration register (`SxCR`)
+const CR_EN: u32 = 1 << 0;
+const CR_DMEIE: u32 = 1 << 1;
+const CR_TEIE: u32 = 1 << 2;
+const CR_HTIE: u32 = 1 << 3;
+const CR_TCIE: u32 = 1 << 4;
+const CR_CIRC: u32 = 1 << 8;
+const CR_DIR_M2P: u32 = 0b01 << 6;
+const CR_MINC: u32 = 1 << 10;
+const CR_PL_HIGH: u32 = 0b10 << 16;
+const CR_CHSEL_SHIFT: u32 = 25;
+
+/// Memory accessed by DMA
+///
+/// Place this in a `static`, outside of CCM RAM.
+pub struct Buffers {
+    rx: [u8; RX_BUFFER_LEN],
+    tx: [u8; TX_BUFFER_LEN],
+}
+
+impl Buffers {
+    /// Create zeroed buffers
+    pub const fn new() -> Self {
+        Buffers {
+            rx: [0; RX_BUFFER_LEN],
+            tx: [0; TX_BUFFER_LEN],
+        }
+    }
+}
+
+impl Default for Buffers {
+    fn default() -> Self {
+        Buffers::new()
+    }
+}
+
+/// Communication channel to the `nRF51` sending and receiving through DMA
+pub struct DmaUartComm {
+    comm: UartComm,
+    dma: DMA2,
+    buffers: &'static mut Buffers,
+    rx_pos: usize,
+    /// Halves of the receive buffer filled by DMA, wrapping
+    rx_filled: u32,
+    /// Bytes handed to the parser, wrapping
+    rx_read: u32,
+    parser: Parser,
+    tx_queue: Queue<syslink::Packet, TxQueueLen>,
+    tx_len: usize,
+    tx_paused: bool,
+}
+
+impl DmaUartComm {
+    /// Start receiving into `buffers` through DMA
+    ///
+    /// # Panics
+    /// If `buffers` are placed in CCM RAM, which DMA can not access.
+    pub fn new(mut comm: UartComm, dma: DMA2, buffers: &'static mut Buffers) -> Self {
+        ccmram::assert_dma_capable(&buffers.rx[..]);
+        ccmram::assert_dma_capable(&buffers.tx[..]);
+        comm.disable_interrupt(serial::Event::Rxne);
+        comm.disable_interrupt(serial::Event::Txe);
+        comm.enable_flow_control_interrupt();
+        // Safety: Only the bit related to DMA2 is changed
+        unsafe {
+            let rcc = &*RCC::ptr();
+            rcc.ahb1enr.modify(|_, w| w.dma2en().set_bit());
+        }
+        // Safety: The USART is owned by `comm`, only the DMA and idle-line settings are changed
+        let usart = unsafe { &*USART6::ptr() };
+        disable_stream(&dma, RX_STREAM);
+        disable_stream(&dma, TX_STREAM);
+        #[allow(unused_unsafe)]
+        unsafe {
+            dma.lifcr.write(|w| w.bits(RX_STREAM_FLAGS));
+            dma.hifcr.write(|w| w.bits(TX_STREAM_FLAGS));
+            let stream = &dma.st[RX_STREAM];
+            stream.par.write(|w| w.bits(&usart.dr as *const _ as u32));
+            stream.m0ar.write(|w| w.bits(buffers.rx.as_ptr() as u32));
+            // Direct mode, byte sized peripheral to memory transfers
+            stream.fcr.reset();
+            // The transmit stream is set up per packet, only the fixed settings are written here
+            let stream = &dma.st[TX_STREAM];
+            stream.par.write(|w| w.bits(&usart.dr as *const _ as u32));
+            stream.fcr.reset();
+        }
+        enable_receive(&dma);
+        usart.cr3.modify(|_, w| w.dmar().set_bit().dmat().set_bit());
+        usart.cr1.modify(|_, w| w.idleie().set_bit());
+        DmaUartComm {
+            comm,
+            dma,
+            buffers,
+            rx_pos: 0,
+            rx_filled: 0,
+            rx_read: 0,
+            parser: Parser::new(),
+            tx_queue: Queue::new(),
+            tx_len: 0,
+            tx_paused: false,
+        }
+    }
+
+    /// Handle the `USART6` idle-line, `DMA2` stream 1 and 6 and flow control interrupts
+    ///
+    /// This clears the interrupt flags, pauses or resumes sending depending on the flow control
+    /// pin and starts sending the next queued packet. Call [`DmaUartComm::receive`] afterwards to
+    /// parse the received data.
+    pub fn on_interrupt(&mut self) {
+        if clear_flow_control_interrupt() {
+            if self.comm.is_busy() {
+                self.pause_transmit();
+            } else {
+                self.resume_transmit();
+            }
+        }
+        // Safety: Reading the status register followed by the data register clears the idle-line
+        // flag, the data register holds no unread data since DMA already read it
+        let usart = unsafe { &*USART6::ptr() };
+        if usart.sr.read().idle().bit_is_set() {
+            let _ = usart.dr.read();
+        }
+        let rx_flags = self.dma.lisr.read().bits() & RX_STREAM_FLAGS;
+        let tx_flags = self.dma.hisr.read().bits() & TX_STREAM_FLAGS;
+        #[allow(unused_unsafe)]
+        unsafe {
+            self.dma.lifcr.write(|w| w.bits(rx_flags));
+            self.dma.hifcr.write(|w| w.bits(tx_flags));
+        }
+        for &flag in RX_HALF_FLAGS.iter() {
+            if rx_flags & flag != 0 {
+                self.rx_filled = self.rx_filled.wrapping_add(1);
+            }
+        }
+        if rx_flags & RX_ERROR_FLAGS != 0 {
+            self.count_transfer_error();
+            // The stream is disabled on errors, receiving starts over at the beginning of the
+            // buffer
+            disable_stream(&self.dma, RX_STREAM);
+            self.parser.clear();
+            self.rx_pos = 0;
+            self.rx_filled = 0;
+            self.rx_read = 0;
+            enable_receive(&self.dma);
+        }
+        if tx_flags & TX_ERROR_FLAGS != 0 {
+            // The stream is disabled on errors, the rest of the packet is not sent
+            self.count_transfer_error();
+            disable_stream(&self.dma, TX_STREAM);
+            self.tx_paused = false;
+        }
+        self.start_transmit();
+    }
+
+    /// Queue a [`Packet`](syslink::Packet) to be sent to the `nRF51` without blocking
+    ///
+    /// If the queue is full the packet is given back.
+    pub fn try_send(&mut self, packet: syslink::Packet) -> Result<(), syslink::Packet> {
+        self.tx_queue.enqueue(packet)?;
+        self.start_transmit();
+        Ok(())
+    }
+
+    /// Number of packets waiting to be sent
+    pub fn queued(&self) -> usize {
+        self.tx_queue.len()
+    }
+
+    /// Start sending the next queued packet, unless a transfer is in progress or paused
+    ///
+    /// The stream is disabled by the hardware when a transfer completes. Packets which can not be
+    /// serialized are dropped.
+    fn start_transmit(&mut self) {
+        let stream = &self.dma.st[TX_STREAM];
+        if self.tx_paused || stream.cr.read().en().bit_is_set() || self.comm.is_busy() {
+            return;
+        }
+        while let Some(packet) = self.tx_queue.dequeue() {
+            let bytes = match packet.write(&mut self.buffers.tx) {
+                Ok(bytes) => bytes,
+                Err(_) => continue,
+            };
+            self.tx_len = bytes;
+            self.parser.stats.sent = self.parser.stats.sent.wrapping_add(1);
+            self.enable_transmit(0, bytes);
+            return;
+        }
+    }
+
+    /// Stop the transfer in progress, keeping track of the bytes not yet sent
+    fn pause_transmit(&mut self) {
+        let stream = &self.dma.st[TX_STREAM];
+        if stream.cr.read().en().bit_is_clear() {
+            return;
+        }
+        disable_stream(&self.dma, TX_STREAM);
+        // Disabling the stream keeps the number of bytes left to transfer
+        self.tx_paused = stream.ndtr.read().bits() != 0;
+        #[allow(unused_unsafe)]
+        self.dma.hifcr.write(|w| unsafe { w.bits(TX_STREAM_FLAGS) });
+    }
+
+    /// Continue a paused transfer from where it stopped
+    fn resume_transmit(&mut self) {
+        if !self.tx_paused {
+            return;
+        }
+        self.tx_paused = false;
+        let remaining = self.dma.st[TX_STREAM].ndtr.read().bits() as usize;
+        self.enable_transmit(self.tx_len - remaining, remaining);
+    }
+
+    /// Send `len` bytes of the transmit buffer starting at `offset`
+    fn enable_transmit(&mut self, offset: usize, len: usize) {
+        let stream = &self.dma.st[TX_STREAM];
+        let start = self.buffers.tx[offset..].as_ptr();
+        #[allow(unused_unsafe)]
+        unsafe {
+            self.dma.hifcr.write(|w| w.bits(TX_STREAM_FLAGS));
+            stream.m0ar.write(|w| w.bits(start as u32));
+            stream.ndtr.write(|w| w.bits(len as u32));
+            stream.cr.write(|w| {
+                w.bits(
+                    (USART6_CHANNEL << CR_CHSEL_SHIFT)
+                        | CR_PL_HIGH
+                        | CR_MINC
+                        | CR_DIR_M2P
+                        | CR_TCIE
+                        | CR_TEIE
+                        | CR_DMEIE
+                        | CR_EN,
+                )
+            });
+        }
+    }
+
+    /// Receive a new [`Packet`](syslink::Packet) from the data buffered by DMA
+    pub fn receive(&mut self) -> nb::Result<syslink::Packet, RecvError> {
+        loop {
+            match self.parser.parse() {
+                Err(nb::Error::WouldBlock) => {}
+                result => return result,
+            }
+            // Hand the next chunk of the circular buffer to the parser
+            let write_pos = RX_BUFFER_LEN - self.dma.st[RX_STREAM].ndtr.read().bits() as usize;
+            let write_pos = write_pos % RX_BUFFER_LEN;
+            self.check_overrun(write_pos);
+            if write_pos == self.rx_pos {
+                return Err(nb::Error::WouldBlock);
+            }
+            let end = if write_pos > self.rx_pos {
+                write_pos
+            } else {
+                RX_BUFFER_LEN
+            };
+            let added = self.parser.extend(&self.buffers.rx[self.rx_pos..end]);
+            if added == 0 {
+                self.parser.stats.overflows = self.parser.stats.overflows.wrapping_add(1);
+                return Err(nb::Error::Other(RecvError::ReceiveBufferFull));
+            }
+            self.rx_pos = (self.rx_pos + added) % RX_BUFFER_LEN;
+            self.rx_read = self.rx_read.wrapping_add(added as u32);
+        }
+    }
+
+    /// Discard the unread data if DMA has written more than a whole buffer since it was last read
+    ///
+    /// The number of written bytes is reconstructed from the filled halves counted in
+    /// [`DmaUartComm::on_interrupt`]. A half transfer interrupt which is still pending makes the
+    /// count fall short, which only delays detection until the interrupt has been handled.
+    fn check_overrun(&mut self, write_pos: usize) {
+        let written = self
+            .rx_filled
+            .wrapping_mul(RX_HALF_LEN as u32)
+            .wrapping_add((write_pos % RX_HALF_LEN) as u32);
+        let unread = written.wrapping_sub(self.rx_read) as i32;
+        if unread <= RX_BUFFER_LEN as i32 {
+            return;
+        }
+        let stats = &mut self.parser.stats;
+        stats.rx_overruns = stats.rx_overruns.wrapping_add(1);
+        // The oldest unread data has been overwritten, including the start of any packet in the
+        // parser
+        self.parser.clear();
+        self.rx_pos = write_pos;
+        self.rx_read = written;
+    }
+
+    /// Increment the transfer error counter
+    fn count_transfer_error(&mut self) {
+        let stats = &mut self.parser.stats;
+        stats.transfer_errors = stats.transfer_errors.wrapping_add(1);
+    }
+
+    /// Empty the parser buffer and discard all data received so far
+    pub fn empty_buffer(&mut self) {
+        self.parser.clear();
+        let write_pos = RX_BUFFER_LEN - self.dma.st[RX_STREAM].ndtr.read().bits() as usize;
+        let write_pos = write_pos % RX_BUFFER_LEN;
+        let unread = (write_pos + RX_BUFFER_LEN - self.rx_pos) % RX_BUFFER_LEN;
+        self.parser.stats.discarded_bytes =
+            self.parser.stats.discarded_bytes.wrapping_add(unread as u32);
+        self.rx_pos = write_pos;
+        self.rx_read = self.rx_read.wrapping_add(unread as u32);
+    }
+
+    /// Snapshot of the link statistics
+    ///
+    /// The statistics of the [`UartComm`] are not carried over when DMA is started.
+    pub fn stats(&self) -> LinkStats {
+        self.parser.stats
+    }
+
+    /// Reset all link statistics to zero
+    pub fn reset_stats(&mut self) {
+        self.parser.stats = LinkStats::default();
+    }
+
+    /// Stop DMA and give back the underlying resources
+    ///
+    /// Packets still waiting in the queue are dropped.
+    pub fn free(self) -> (UartComm, DMA2, &'static mut Buffers) {
+        // Safety: Reverts the changes to the USART made in `new`
+        let usart = unsafe { &*USART6::ptr() };
+        usart.cr1.modify(|_, w| w.idleie().clear_bit());
+        usart.cr3.modify(|_, w| w.dmar().clear_bit().dmat().clear_bit());
+        disable_stream(&self.dma, RX_STREAM);
+        disable_stream(&self.dma, TX_STREAM);
+        #[allow(unused_unsafe)]
+        unsafe {
+            self.dma.lifcr.write(|w| w.bits(RX_STREAM_FLAGS));
+            self.dma.hifcr.write(|w| w.bits(TX_STREAM_FLAGS));
+        }
+        (self.comm, self.dma, self.buffers)
+    }
+}
+
+/// Start receiving into the circular buffer from its beginning
+///
+/// The peripheral and memory addresses are set up in [`DmaUartComm::new`].
+fn enable_receive(dma: &DMA2) {
+    let stream = &dma.st[RX_STREAM];
+    #[allow(unused_unsafe)]
+    unsafe {
+        dma.lifcr.write(|w| w.bits(RX_STREAM_FLAGS));
+        stream.ndtr.write(|w| w.bits(RX_BUFFER_LEN as u32));
+        stream.cr.write(|w| {
+            w.bits(
+                (USART6_CHANNEL << CR_CHSEL_SHIFT)
+                    | CR_PL_HIGH
+                    | CR_MINC
+                    | CR_CIRC
+                    | CR_TCIE
+                    | CR_HTIE
+                    | CR_TEIE
+                    | CR_DMEIE
+                    | CR_EN,
+            )
+        });
+    }
+}
+
+/// Disable a DMA stream and wait until the current transfer has stopped
+fn disable_stream(dma: &DMA2, stream: usize) {
+    let stream = &dma.st[stream];
+    stream.cr.modify(|_, w| w.en().clear_bit());
+    while stream.cr.read().en().bit_is_set() {}
+}
@@ -0,0 +1,71 @@
+//! In-memory serial transport for host tests
+use crate::hal::nb;
+use core::convert::Infallible;
+use embedded_hal::serial::{Read, Write};
+use std::collections::VecDeque;
+
+/// Serial transport which, like the `nRF51` for configuration packets, echoes every written byte
+pub(crate) struct Loopback {
+    /// Bytes waiting to be read
+    pub(crate) rx: VecDeque<u8>,
+    echo: bool,
+}
+
+impl Loopback {
+    /// Transport receiving every byte written to it
+    pub(crate) fn new() -> Self {
+        Loopback {
+            rx: VecDeque::new(),
+            echo: true,
+        }
+    }
+
+    /// Transport discarding every byte written to it
+    pub(crate) fn silent() -> Self {
+        Loopback {
+            rx: VecDeque::new(),
+            echo: false,
+        }
+    }
+
+    /// Queue a packet to be read
+    pub(crate) fn inject(&mut self, packet: syslink::Packet) {
+        let mut buffer = [0u8; 72];
+        let len = packet
+            .write(&mut buffer)
+            .unwrap_or_else(|_| panic!("packet does not fit"));
+        self.rx.extend(&buffer[..len]);
+    }
+}
+
+impl Read<u8> for Loopback {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl Write<u8> for Loopback {
+    type Error = Infallible;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.echo {
+            self.rx.push_back(byte);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Clock advancing by `step_us` microseconds every time it is read
+pub(crate) fn clock(step_us: u64) -> impl FnMut() -> u64 {
+    let mut now = 0;
+    move || {
+        now += step_us;
+        now
+    }
+}
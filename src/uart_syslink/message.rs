@@ -0,0 +1,444 @@
+//! Typed [`syslink`] messages
+//!
+//! The packet types are grouped the same way as in the [official
+//! firmware](https://github.com/bitcraze/crazyflie-firmware/blob/master/src/hal/interface/syslink.h):
+//! radio, power management, one-wire memory and system. [`Message::decode`] turns a raw
+//! [`Packet`](syslink::Packet) into the matching typed message.
+use core::convert::TryInto;
+
+/// Packet types of the radio group
+pub mod radio {
+    pub const RAW: u8 = 0x00;
+    pub const CHANNEL: u8 = 0x01;
+    pub const DATARATE: u8 = 0x02;
+    pub const CONTWAVE: u8 = 0x03;
+    pub const RSSI: u8 = 0x04;
+    pub const ADDRESS: u8 = 0x05;
+    pub const RAW_BROADCAST: u8 = 0x06;
+    pub const POWER: u8 = 0x07;
+    pub const P2P: u8 = 0x08;
+    pub const P2P_ACK: u8 = 0x09;
+    pub const P2P_BROADCAST: u8 = 0x0A;
+}
+
+/// Packet types of the power management group
+pub mod pm {
+    pub const SOURCE: u8 = 0x10;
+    pub const ONOFF_SWITCHOFF: u8 = 0x11;
+    pub const BATTERY_VOLTAGE: u8 = 0x12;
+    pub const BATTERY_STATE: u8 = 0x13;
+    pub const BATTERY_AUTOUPDATE: u8 = 0x14;
+}
+
+/// Packet types of the one-wire memory group
+pub mod ow {
+    pub const SCAN: u8 = 0x20;
+    pub const GETINFO: u8 = 0x21;
+    pub const READ: u8 = 0x22;
+    pub const WRITE: u8 = 0x23;
+}
+
+/// Packet types of the system group
+pub mod sys {
+    pub const NRF_VERSION: u8 = 0x30;
+}
+
+/// Group of a packet type, encoded in the upper nibble
+const GROUP_MASK: u8 = 0xF0;
+const GROUP_RADIO: u8 = 0x00;
+const GROUP_PM: u8 = 0x10;
+const GROUP_OW: u8 = 0x20;
+const GROUP_SYS: u8 = 0x30;
+
+/// The payload of a packet was too short or invalid for its type
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Type of the packet
+    pub packet_type: u8,
+    /// Length of the payload
+    pub len: usize,
+}
+
+/// Data rate of the radio
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DataRate {
+    Rate250K = 0,
+    Rate1M = 1,
+    Rate2M = 2,
+}
+
+impl DataRate {
+    /// Convert from the value used in syslink packets and the config block
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(DataRate::Rate250K),
+            1 => Some(DataRate::Rate1M),
+            2 => Some(DataRate::Rate2M),
+            _ => None,
+        }
+    }
+}
+
+/// Radio configuration of the `nRF51`
+///
+/// The `nRF51` echoes configuration packets back once they have been applied, these are decoded
+/// as [`Radio::Ack`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RadioSetting {
+    /// Channel between `0` and `125`
+    Channel(u8),
+    DataRate(DataRate),
+    /// Continuous wave test mode
+    ContinuousWave(bool),
+    /// 40-bit address
+    Address(u64),
+    /// Transmit power in dBm, e.g. `0` or `-12`
+    Power(i8),
+}
+
+impl RadioSetting {
+    /// Packet requesting the `nRF51` to apply the setting
+    pub fn packet(&self) -> syslink::Packet {
+        match *self {
+            RadioSetting::Channel(channel) => syslink::Packet::new(radio::CHANNEL, &[channel]),
+            RadioSetting::DataRate(rate) => syslink::Packet::new(radio::DATARATE, &[rate as u8]),
+            RadioSetting::ContinuousWave(enabled) => {
+                syslink::Packet::new(radio::CONTWAVE, &[enabled as u8])
+            }
+            RadioSetting::Address(address) => {
+                syslink::Packet::new(radio::ADDRESS, &address.to_le_bytes()[..5])
+            }
+            RadioSetting::Power(power) => syslink::Packet::new(radio::POWER, &[power as u8]),
+        }
+    }
+}
+
+/// Messages of the radio group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Radio<'a> {
+    /// Packet received over the radio
+    Raw(&'a [u8]),
+    /// Broadcast packet received over the radio
+    RawBroadcast(&'a [u8]),
+    /// Signal strength of the last acknowledgement, in `-dBm`
+    Rssi(u8),
+    /// Configuration acknowledged by the `nRF51`
+    Ack(RadioSetting),
+    /// Peer to peer packet
+    P2p(&'a [u8]),
+    /// Acknowledgement of a peer to peer packet
+    P2pAck(&'a [u8]),
+    /// Peer to peer broadcast packet
+    P2pBroadcast(&'a [u8]),
+}
+
+/// Payload of a battery state packet
+///
+/// See [`BatteryState`](crate::power::BatteryState) for the interpreted state.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BatteryPayload {
+    /// Charger flags
+    pub flags: u8,
+    /// Battery voltage in volts
+    pub voltage: f32,
+    /// Charge current in amperes
+    pub charge_current: f32,
+}
+
+/// Messages of the power management group
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Power {
+    /// Power source, `0` for battery and `1` for USB
+    Source(u8),
+    /// The `nRF51` is about to switch off the Crazyflie
+    SwitchOff,
+    /// Battery voltage in volts
+    BatteryVoltage(f32),
+    /// Charging state, battery voltage and charge current
+    BatteryState(BatteryPayload),
+    /// Automatic battery updates were enabled
+    BatteryAutoUpdate,
+}
+
+/// Messages of the one-wire memory group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OneWire<'a> {
+    Scan(&'a [u8]),
+    GetInfo(&'a [u8]),
+    Read(&'a [u8]),
+    Write(&'a [u8]),
+}
+
+/// Messages of the system group
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum System<'a> {
+    /// Version string of the `nRF51` firmware
+    NrfVersion(&'a [u8]),
+}
+
+/// A decoded syslink message
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Message<'a> {
+    Radio(Radio<'a>),
+    Power(Power),
+    OneWire(OneWire<'a>),
+    System(System<'a>),
+    /// Packet of a type which is not known
+    Unknown { packet_type: u8, data: &'a [u8] },
+}
+
+impl<'a> Message<'a> {
+    /// Decode a [`Packet`](syslink::Packet) into a typed message
+    pub fn decode(packet: &'a syslink::Packet) -> Result<Self, DecodeError> {
+        Message::from_parts(packet.packet_type(), packet.data())
+    }
+
+    /// Decode a message from its packet type and payload
+    pub fn from_parts(packet_type: u8, data: &'a [u8]) -> Result<Self, DecodeError> {
+        let error = DecodeError {
+            packet_type,
+            len: data.len(),
+        };
+        let byte = |i: usize| data.get(i).copied().ok_or(error);
+        let float = |i: usize| {
+            data.get(i..i + 4)
+                // Unwrap safety: The slice has exactly four bytes
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .ok_or(error)
+        };
+        let message = match packet_type & GROUP_MASK {
+            GROUP_RADIO => Message::Radio(match packet_type {
+                radio::RAW => Radio::Raw(data),
+                radio::RAW_BROADCAST => Radio::RawBroadcast(data),
+                radio::RSSI => Radio::Rssi(byte(0)?),
+                radio::CHANNEL => Radio::Ack(RadioSetting::Channel(byte(0)?)),
+                radio::DATARATE => {
+                    let rate = DataRate::from_u8(byte(0)?).ok_or(error)?;
+                    Radio::Ack(RadioSetting::DataRate(rate))
+                }
+                radio::CONTWAVE => Radio::Ack(RadioSetting::ContinuousWave(byte(0)? != 0)),
+                radio::POWER => Radio::Ack(RadioSetting::Power(byte(0)? as i8)),
+                radio::ADDRESS => {
+                    let bytes = data.get(..5).ok_or(error)?;
+                    let address = bytes
+                        .iter()
+                        .rev()
+                        .fold(0u64, |address, b| (address << 8) | *b as u64);
+                    Radio::Ack(RadioSetting::Address(address))
+                }
+                radio::P2P => Radio::P2p(data),
+                radio::P2P_ACK => Radio::P2pAck(data),
+                radio::P2P_BROADCAST => Radio::P2pBroadcast(data),
+                _ => return Ok(Message::Unknown { packet_type, data }),
+            }),
+            GROUP_PM => Message::Power(match packet_type {
+                pm::SOURCE => Power::Source(byte(0)?),
+                pm::ONOFF_SWITCHOFF => Power::SwitchOff,
+                pm::BATTERY_VOLTAGE => Power::BatteryVoltage(float(0)?),
+                pm::BATTERY_STATE => Power::BatteryState(BatteryPayload {
+                    flags: byte(0)?,
+                    voltage: float(1)?,
+                    charge_current: float(5)?,
+                }),
+                pm::BATTERY_AUTOUPDATE => Power::BatteryAutoUpdate,
+                _ => return Ok(Message::Unknown { packet_type, data }),
+            }),
+            GROUP_OW => Message::OneWire(match packet_type {
+                ow::SCAN => OneWire::Scan(data),
+                ow::GETINFO => OneWire::GetInfo(data),
+                ow::READ => OneWire::Read(data),
+                ow::WRITE => OneWire::Write(data),
+                _ => return Ok(Message::Unknown { packet_type, data }),
+            }),
+            GROUP_SYS => Message::System(match packet_type {
+                sys::NRF_VERSION => System::NrfVersion(data),
+                _ => return Ok(Message::Unknown { packet_type, data }),
+            }),
+            _ => Message::Unknown { packet_type, data },
+        };
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(packet_type: u8, data: &[u8]) -> Result<Message<'_>, DecodeError> {
+        Message::from_parts(packet_type, data)
+    }
+
+    fn short(packet_type: u8, len: usize) -> Result<Message<'static>, DecodeError> {
+        Err(DecodeError { packet_type, len })
+    }
+
+    #[test]
+    fn radio_packets() {
+        let data = [1, 2, 3];
+        assert_eq!(
+            decode(radio::RAW, &data),
+            Ok(Message::Radio(Radio::Raw(&data)))
+        );
+        assert_eq!(
+            decode(radio::RAW_BROADCAST, &data),
+            Ok(Message::Radio(Radio::RawBroadcast(&data)))
+        );
+        assert_eq!(
+            decode(radio::P2P, &data),
+            Ok(Message::Radio(Radio::P2p(&data)))
+        );
+        assert_eq!(
+            decode(radio::P2P_ACK, &data),
+            Ok(Message::Radio(Radio::P2pAck(&data)))
+        );
+        assert_eq!(
+            decode(radio::P2P_BROADCAST, &data),
+            Ok(Message::Radio(Radio::P2pBroadcast(&data)))
+        );
+        assert_eq!(
+            decode(radio::RSSI, &[42]),
+            Ok(Message::Radio(Radio::Rssi(42)))
+        );
+    }
+
+    #[test]
+    fn radio_acks() {
+        let ack = |setting| Ok(Message::Radio(Radio::Ack(setting)));
+        assert_eq!(
+            decode(radio::CHANNEL, &[80]),
+            ack(RadioSetting::Channel(80))
+        );
+        assert_eq!(
+            decode(radio::DATARATE, &[2]),
+            ack(RadioSetting::DataRate(DataRate::Rate2M))
+        );
+        assert_eq!(
+            decode(radio::CONTWAVE, &[1]),
+            ack(RadioSetting::ContinuousWave(true))
+        );
+        assert_eq!(decode(radio::POWER, &[0xF4]), ack(RadioSetting::Power(-12)));
+        // The address is sent as five bytes, least significant first
+        assert_eq!(
+            decode(radio::ADDRESS, &[0x01, 0x02, 0x03, 0x04, 0x05]),
+            ack(RadioSetting::Address(0x05_0403_0201))
+        );
+    }
+
+    #[test]
+    fn radio_settings_round_trip() {
+        let settings = [
+            RadioSetting::Channel(125),
+            RadioSetting::DataRate(DataRate::Rate250K),
+            RadioSetting::ContinuousWave(false),
+            RadioSetting::Address(0xE7_E7E7_E7E7),
+            RadioSetting::Power(-20),
+        ];
+        for &setting in settings.iter() {
+            let packet = setting.packet();
+            assert_eq!(
+                Message::decode(&packet),
+                Ok(Message::Radio(Radio::Ack(setting)))
+            );
+        }
+    }
+
+    #[test]
+    fn power_packets() {
+        let voltage = 3.7f32.to_le_bytes();
+        let mut state = [0u8; 9];
+        state[0] = 0b11;
+        state[1..5].copy_from_slice(&voltage);
+        state[5..].copy_from_slice(&0.5f32.to_le_bytes());
+        assert_eq!(
+            decode(pm::SOURCE, &[1]),
+            Ok(Message::Power(Power::Source(1)))
+        );
+        assert_eq!(
+            decode(pm::ONOFF_SWITCHOFF, &[]),
+            Ok(Message::Power(Power::SwitchOff))
+        );
+        assert_eq!(
+            decode(pm::BATTERY_VOLTAGE, &voltage),
+            Ok(Message::Power(Power::BatteryVoltage(3.7)))
+        );
+        assert_eq!(
+            decode(pm::BATTERY_STATE, &state),
+            Ok(Message::Power(Power::BatteryState(BatteryPayload {
+                flags: 0b11,
+                voltage: 3.7,
+                charge_current: 0.5,
+            })))
+        );
+        assert_eq!(
+            decode(pm::BATTERY_AUTOUPDATE, &[]),
+            Ok(Message::Power(Power::BatteryAutoUpdate))
+        );
+    }
+
+    #[test]
+    fn one_wire_and_system_packets() {
+        let data = [4, 5];
+        assert_eq!(
+            decode(ow::SCAN, &data),
+            Ok(Message::OneWire(OneWire::Scan(&data)))
+        );
+        assert_eq!(
+            decode(ow::GETINFO, &data),
+            Ok(Message::OneWire(OneWire::GetInfo(&data)))
+        );
+        assert_eq!(
+            decode(ow::READ, &data),
+            Ok(Message::OneWire(OneWire::Read(&data)))
+        );
+        assert_eq!(
+            decode(ow::WRITE, &data),
+            Ok(Message::OneWire(OneWire::Write(&data)))
+        );
+        assert_eq!(
+            decode(sys::NRF_VERSION, b"2024.2"),
+            Ok(Message::System(System::NrfVersion(b"2024.2")))
+        );
+    }
+
+    #[test]
+    fn unknown_packets() {
+        let data = [7];
+        for &packet_type in [0x0F, 0x1F, 0x2F, 0x3F, 0x40, 0xFF].iter() {
+            assert_eq!(
+                decode(packet_type, &data),
+                Ok(Message::Unknown {
+                    packet_type,
+                    data: &data
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn short_payloads() {
+        assert_eq!(decode(radio::RSSI, &[]), short(radio::RSSI, 0));
+        assert_eq!(decode(radio::CHANNEL, &[]), short(radio::CHANNEL, 0));
+        assert_eq!(decode(radio::DATARATE, &[]), short(radio::DATARATE, 0));
+        assert_eq!(decode(radio::CONTWAVE, &[]), short(radio::CONTWAVE, 0));
+        assert_eq!(decode(radio::POWER, &[]), short(radio::POWER, 0));
+        assert_eq!(
+            decode(radio::ADDRESS, &[1, 2, 3, 4]),
+            short(radio::ADDRESS, 4)
+        );
+        assert_eq!(decode(pm::SOURCE, &[]), short(pm::SOURCE, 0));
+        assert_eq!(
+            decode(pm::BATTERY_VOLTAGE, &[0; 3]),
+            short(pm::BATTERY_VOLTAGE, 3)
+        );
+        assert_eq!(
+            decode(pm::BATTERY_STATE, &[0; 8]),
+            short(pm::BATTERY_STATE, 8)
+        );
+    }
+
+    #[test]
+    fn invalid_data_rate() {
+        assert_eq!(decode(radio::DATARATE, &[3]), short(radio::DATARATE, 1));
+    }
+}
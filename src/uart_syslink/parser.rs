@@ -0,0 +1,213 @@
+//! Incremental parsing of [`syslink`] packets from a byte stream
+use super::{LinkStats, RecvError};
+use crate::hal::nb;
+use heapless::{consts::U70, Vec};
+
+/// Bytes starting every syslink packet
+const START_OF_FRAME: [u8; 2] = [0xBC, 0xCF];
+
+/// Buffer of received bytes which are parsed into [`Packet`](syslink::Packet)s
+pub(crate) struct Parser {
+    buffer: Vec<u8, U70>,
+    /// Link statistics, the receive counters are updated by the parser
+    pub(crate) stats: LinkStats,
+}
+
+impl Parser {
+    /// Create an empty parser
+    pub(crate) fn new() -> Self {
+        Parser {
+            buffer: Vec::new(),
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// Add a single byte to the buffer
+    pub(crate) fn push<E>(&mut self, byte: u8) -> Result<(), RecvError<E>> {
+        // Overflow here should "never" happen, but if it does we signal it so that calling users
+        // can clear the buffer
+        self.buffer.push(byte).map_err(|_| {
+            self.stats.overflows = self.stats.overflows.wrapping_add(1);
+            RecvError::ReceiveBufferFull
+        })
+    }
+
+    /// Add as many bytes as fit in the buffer, returning the number of bytes added
+    pub(crate) fn extend(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(self.buffer.capacity() - self.buffer.len());
+        // Unwrap safety: The number of bytes is limited to the remaining capacity
+        self.buffer.extend_from_slice(&data[..count]).unwrap();
+        count
+    }
+
+    /// Try to parse a packet from the start of the buffer
+    pub(crate) fn parse<E>(&mut self) -> nb::Result<syslink::Packet, RecvError<E>> {
+        match syslink::Packet::from(&self.buffer) {
+            // If we parsed a complete packet, keep the remaining bytes and return the packet
+            Ok((slice, packet)) => {
+                // Unwrap safety: Since the slice already comes from 'buffer' this can never fail
+                self.buffer = Vec::from_slice(&slice).unwrap();
+                self.stats.received = self.stats.received.wrapping_add(1);
+                Ok(packet)
+            }
+            // Not yet done parsing, do nothing with the internal buffer and signal blockage
+            Err(syslink::ParseError::Incomplete(_)) => Err(nb::Error::WouldBlock),
+            // The other parsing errors require us to truncate the underlying receive buffer before
+            // returning the error. Only the start of frame is dropped, the length of a corrupt
+            // packet can not be trusted and the bytes following it may contain a valid packet.
+            Err(e @ syslink::ParseError::WrongTag) => {
+                self.stats.wrong_tag = self.stats.wrong_tag.wrapping_add(1);
+                self.truncate_first(1);
+                Err(nb::Error::Other(RecvError::Syslink(e)))
+            }
+            Err(syslink::ParseError::TooMuchData(tr)) => {
+                self.stats.too_much_data = self.stats.too_much_data.wrapping_add(1);
+                self.truncate_first(1);
+                Err(nb::Error::Other(RecvError::Syslink(
+                    syslink::ParseError::TooMuchData(tr),
+                )))
+            }
+            Err(syslink::ParseError::WrongChecksum(tr)) => {
+                self.stats.checksum_errors = self.stats.checksum_errors.wrapping_add(1);
+                self.truncate_first(1);
+                Err(nb::Error::Other(RecvError::Syslink(
+                    syslink::ParseError::WrongChecksum(tr),
+                )))
+            }
+        }
+    }
+
+    /// Remove the first `bytes` from the buffer and resynchronize on the next start of frame
+    ///
+    /// This method is used when a parse error occurs and we need to discard a given number of
+    /// bytes from the start of the buffer. Any bytes before the next start of frame can not be
+    /// part of a valid packet and are discarded as well.
+    fn truncate_first(&mut self, bytes: usize) {
+        let rest = &self.buffer[bytes.min(self.buffer.len())..];
+        let start = find_start_of_frame(rest);
+        let discarded = self.buffer.len() - rest.len() + start;
+        // Unwrap safety: Since the slice already comes from 'buffer' this can never fail
+        self.buffer = Vec::from_slice(&rest[start..]).unwrap();
+        self.discarded(discarded);
+    }
+
+    /// Discard all buffered bytes
+    pub(crate) fn clear(&mut self) {
+        self.discarded(self.buffer.len());
+        self.buffer.clear();
+    }
+
+    /// Count discarded bytes
+    fn discarded(&mut self, bytes: usize) {
+        self.stats.discarded_bytes = self.stats.discarded_bytes.wrapping_add(bytes as u32);
+    }
+}
+
+/// Index of the first possible start of frame in `data`
+///
+/// A trailing first start byte is kept since the second byte may not have been received yet. If
+/// no start of frame is found the length of `data` is returned.
+fn find_start_of_frame(data: &[u8]) -> usize {
+    if let Some(pos) = data.windows(2).position(|w| w == START_OF_FRAME) {
+        pos
+    } else if data.last() == Some(&START_OF_FRAME[0]) {
+        data.len() - 1
+    } else {
+        data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Bytes appended to a stream so that a corrupt packet claiming a long payload is completed
+    const PADDING: [u8; 70] = [0; 70];
+
+    /// Serialize a packet into a complete frame
+    fn frame(packet_type: u8, data: &[u8]) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; 72];
+        let len = syslink::Packet::new(packet_type, data)
+            .write(&mut buf)
+            .unwrap_or_else(|_| panic!("packet does not fit"));
+        buf[..len].to_vec()
+    }
+
+    /// Feed a stream byte by byte, returning the type and data of all parsed packets
+    ///
+    /// Every call to `parse` which does not block must shrink the buffer.
+    fn parse_stream(stream: &[u8]) -> std::vec::Vec<(u8, std::vec::Vec<u8>)> {
+        let mut parser = Parser::new();
+        let mut packets = std::vec::Vec::new();
+        for &byte in stream {
+            if parser.push::<()>(byte).is_err() {
+                parser.clear();
+                parser.push::<()>(byte).unwrap();
+            }
+            loop {
+                let len = parser.buffer.len();
+                match parser.parse::<()>() {
+                    Ok(packet) => packets.push((packet.packet_type(), packet.data().to_vec())),
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(_)) => {}
+                }
+                assert!(parser.buffer.len() < len, "parser made no progress");
+            }
+        }
+        packets
+    }
+
+    fn packet() -> impl Strategy<Value = (u8, std::vec::Vec<u8>)> {
+        (any::<u8>(), prop::collection::vec(any::<u8>(), 0..=64))
+    }
+
+    #[test]
+    fn resyncs_inside_corrupt_frame() {
+        let mut first = frame(0x00, &[1]);
+        // Claim a longer payload so that the following packets are read as its data
+        first[3] = 20;
+        let mut stream = first;
+        for data in [[2, 3], [4, 5], [6, 7]].iter() {
+            stream.extend(frame(0x00, data));
+        }
+        let packets = parse_stream(&stream);
+        assert_eq!(
+            packets,
+            vec![(0x00, vec![2, 3]), (0x00, vec![4, 5]), (0x00, vec![6, 7])]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn random_stream_makes_progress(stream in prop::collection::vec(any::<u8>(), 0..1024)) {
+            parse_stream(&stream);
+        }
+
+        #[test]
+        fn finds_packet_after_garbage(
+            garbage in prop::collection::vec(any::<u8>(), 0..256),
+            (packet_type, data) in packet(),
+        ) {
+            let mut stream = garbage;
+            stream.extend(frame(packet_type, &data));
+            stream.extend(&PADDING);
+            let packets = parse_stream(&stream);
+            prop_assert_eq!(packets.last(), Some(&(packet_type, data)));
+        }
+
+        #[test]
+        fn recovers_after_corrupt_packet(
+            packets in prop::collection::vec(packet(), 2..6),
+            index in any::<prop::sample::Index>(),
+            flip in 1..=255u8,
+        ) {
+            let frames: std::vec::Vec<_> = packets.iter().map(|(t, d)| frame(*t, d)).collect();
+            let mut stream = frames.concat();
+            stream[index.index(frames[0].len())] ^= flip;
+            stream.extend(&PADDING);
+            let parsed = parse_stream(&stream);
+            prop_assert!(parsed.ends_with(&packets[1..]));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Configuration of the `nRF51` radio
+//!
+//! Each setting is sent as a syslink packet which the `nRF51` echoes back once it has been
+//! applied. The functions on [`UartComm`] block until this acknowledgement is received, or for at
+//! most [`ACK_TIMEOUT_US`] measured with the given time source, see [`UartComm::wait_for`].
+//! Packets received while waiting are discarded and counted in
+//! [`LinkStats::dropped`](super::LinkStats::dropped), the functions are therefore meant to be used
+//! while starting up, before radio traffic is expected.
+//!
+//! With [`DmaUartComm`](super::dma::DmaUartComm) queue [`RadioSetting::packet`] instead and watch
+//! for [`Radio::Ack`] in a [`Handler`](super::dispatch::Handler).
+use super::message::{DataRate, Message, Radio, RadioSetting};
+use super::{SendError, UartComm};
+#[cfg(feature = "eeprom")]
+use crate::eeprom::ConfigBlock;
+use core::convert::Infallible;
+use embedded_hal::digital::v2::InputPin;
+use embedded_hal::serial::{Read, Write};
+
+/// Highest radio channel
+pub const MAX_CHANNEL: u8 = 125;
+/// Time to wait for the acknowledgement of a setting in microseconds
+pub const ACK_TIMEOUT_US: u64 = 10_000;
+
+/// Potential errors when configuring the radio
+pub enum RadioError<E = crate::hal::serial::Error> {
+    /// Problem sending the setting
+    Send(SendError<E>),
+    /// The `nRF51` did not acknowledge the setting
+    NoAck,
+    /// The setting is out of range
+    Invalid,
+}
+
+impl<S, F, RE, WE> UartComm<S, F>
+where
+    S: Read<u8, Error = RE> + Write<u8, Error = WE>,
+    F: InputPin<Error = Infallible>,
+{
+    /// Set the radio channel, between `0` and [`MAX_CHANNEL`]
+    pub fn set_radio_channel(
+        &mut self,
+        now: impl FnMut() -> u64,
+        channel: u8,
+    ) -> Result<(), RadioError<WE>> {
+        if channel > MAX_CHANNEL {
+            return Err(RadioError::Invalid);
+        }
+        self.apply_radio_setting(now, RadioSetting::Channel(channel))
+    }
+
+    /// Set the radio data rate
+    pub fn set_radio_data_rate(
+        &mut self,
+        now: impl FnMut() -> u64,
+        rate: DataRate,
+    ) -> Result<(), RadioError<WE>> {
+        self.apply_radio_setting(now, RadioSetting::DataRate(rate))
+    }
+
+    /// Set the 40-bit radio address
+    pub fn set_radio_address(
+        &mut self,
+        now: impl FnMut() -> u64,
+        address: u64,
+    ) -> Result<(), RadioError<WE>> {
+        if address >> 40 != 0 {
+            return Err(RadioError::Invalid);
+        }
+        self.apply_radio_setting(now, RadioSetting::Address(address))
+    }
+
+    /// Set the radio transmit power in dBm
+    pub fn set_radio_power(
+        &mut self,
+        now: impl FnMut() -> u64,
+        power: i8,
+    ) -> Result<(), RadioError<WE>> {
+        self.apply_radio_setting(now, RadioSetting::Power(power))
+    }
+
+    /// Enable or disable the continuous wave test mode of the radio
+    pub fn set_continuous_wave(
+        &mut self,
+        now: impl FnMut() -> u64,
+        enabled: bool,
+    ) -> Result<(), RadioError<WE>> {
+        self.apply_radio_setting(now, RadioSetting::ContinuousWave(enabled))
+    }
+
+    /// Apply the radio channel, data rate and address of the config block
+    #[cfg(feature = "eeprom")]
+    pub fn configure_radio(
+        &mut self,
+        mut now: impl FnMut() -> u64,
+        config: &ConfigBlock,
+    ) -> Result<(), RadioError<WE>> {
+        let rate = DataRate::from_u8(config.radio_speed).ok_or(RadioError::Invalid)?;
+        self.set_radio_channel(&mut now, config.radio_channel)?;
+        self.set_radio_data_rate(&mut now, rate)?;
+        self.set_radio_address(now, config.radio_address)
+    }
+
+    /// Send a setting and wait for the `nRF51` to echo it back
+    fn apply_radio_setting(
+        &mut self,
+        now: impl FnMut() -> u64,
+        setting: RadioSetting,
+    ) -> Result<(), RadioError<WE>> {
+        self.send(setting.packet()).map_err(RadioError::Send)?;
+        let is_ack = |packet: &syslink::Packet| {
+            matches!(
+                Message::decode(packet),
+                Ok(Message::Radio(Radio::Ack(ack))) if ack == setting
+            )
+        };
+        self.wait_for(now, ACK_TIMEOUT_US, is_ack)
+            .map(|_| ())
+            .ok_or(RadioError::NoAck)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart_syslink::loopback::{clock, Loopback};
+    use crate::uart_syslink::message::radio::RSSI;
+    use crate::uart_syslink::NoFlowControl;
+
+    #[test]
+    fn setting_is_acknowledged() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        assert!(comm.set_radio_channel(clock(1), 80).is_ok());
+        assert!(comm.set_radio_data_rate(clock(1), DataRate::Rate2M).is_ok());
+        assert!(comm.set_radio_address(clock(1), 0xE7E7_E7E7_E7).is_ok());
+        assert!(comm.set_radio_power(clock(1), -12).is_ok());
+        assert_eq!(comm.stats().dropped, 0);
+    }
+
+    #[test]
+    fn missing_ack_times_out() {
+        let mut comm = UartComm::from_serial(Loopback::silent(), NoFlowControl);
+        let result = comm.set_radio_channel(clock(100), 80);
+        assert!(matches!(result, Err(RadioError::NoAck)));
+        assert_eq!(comm.stats().sent, 1);
+    }
+
+    #[test]
+    fn other_packets_are_dropped() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        comm.conn.inject(syslink::Packet::new(RSSI, &[40]));
+        comm.conn.inject(RadioSetting::Channel(2).packet());
+        assert!(comm.set_radio_channel(clock(1), 80).is_ok());
+        assert_eq!(comm.stats().dropped, 2);
+    }
+
+    #[test]
+    fn invalid_settings_are_not_sent() {
+        let mut comm = UartComm::from_serial(Loopback::new(), NoFlowControl);
+        let result = comm.set_radio_channel(clock(1), MAX_CHANNEL + 1);
+        assert!(matches!(result, Err(RadioError::Invalid)));
+        let result = comm.set_radio_address(clock(1), 1 << 40);
+        assert!(matches!(result, Err(RadioError::Invalid)));
+        assert_eq!(comm.stats().sent, 0);
+    }
+}
@@ -0,0 +1,66 @@
+//! Counters of the syslink connection
+use crate::logging::{LogValue, LogVariable, Loggable};
+
+/// Snapshot of the syslink link statistics
+///
+/// All counters wrap around on overflow.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Packets successfully received
+    pub received: u32,
+    /// Packets sent
+    pub sent: u32,
+    /// Packets received with a wrong checksum
+    pub checksum_errors: u32,
+    /// Resynchronizations because the stream did not start with a start of frame
+    pub wrong_tag: u32,
+    /// Packets received with a length larger than allowed
+    pub too_much_data: u32,
+    /// Times the receive buffer filled up
+    pub overflows: u32,
+    /// Received bytes which were discarded
+    pub discarded_bytes: u32,
+    /// Valid packets discarded while waiting for an answer, see
+    /// [`UartComm::wait_for`](super::UartComm::wait_for)
+    pub dropped: u32,
+    /// Times DMA wrapped around the receive buffer and overwrote data which was not yet read
+    pub rx_overruns: u32,
+    /// DMA transfer and direct mode errors of the receive and transmit streams
+    pub transfer_errors: u32,
+}
+
+impl LinkStats {
+    /// Total number of receive errors
+    pub fn errors(&self) -> u32 {
+        self.checksum_errors
+            .wrapping_add(self.wrong_tag)
+            .wrapping_add(self.too_much_data)
+            .wrapping_add(self.overflows)
+            .wrapping_add(self.rx_overruns)
+            .wrapping_add(self.transfer_errors)
+    }
+}
+
+impl Loggable for LinkStats {
+    fn log(&self, f: &mut dyn FnMut(LogVariable)) {
+        let counters = [
+            ("rxPackets", self.received),
+            ("txPackets", self.sent),
+            ("checksumErr", self.checksum_errors),
+            ("wrongTag", self.wrong_tag),
+            ("tooMuchData", self.too_much_data),
+            ("overflows", self.overflows),
+            ("discarded", self.discarded_bytes),
+            ("dropped", self.dropped),
+            ("rxOverruns", self.rx_overruns),
+            ("dmaErrors", self.transfer_errors),
+        ];
+        for &(name, value) in counters.iter() {
+            f(LogVariable {
+                group: "syslink",
+                name,
+                value: LogValue::U32(value),
+            });
+        }
+    }
+}
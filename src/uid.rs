@@ -0,0 +1,65 @@
+//! Unique device ID of the `STM32F405`
+//!
+//! Every `STM32F405` has a factory programmed 96-bit unique ID. This module derives a stable
+//! default radio address and device name from it so that Crazyflies without a valid config block
+//! in EEPROM do not all share the same radio address.
+use core::ptr;
+use core::str;
+
+/// Address of the 96-bit unique ID
+const UID_ADDRESS: usize = 0x1FFF_7A10;
+/// Upper byte of derived radio addresses, the same as in the official default address
+const RADIO_ADDRESS_UPPER: u64 = 0xE7;
+/// Prefix of the derived device name
+const NAME_PREFIX: &[u8] = b"CF-";
+/// Length of the derived device name
+const NAME_LEN: usize = NAME_PREFIX.len() + 8;
+
+/// Read the 96-bit unique ID
+pub fn read() -> [u8; 12] {
+    let mut uid = [0u8; 12];
+    for (i, byte) in uid.iter_mut().enumerate() {
+        // Safety: The unique ID is a read-only system memory region which is always accessible
+        *byte = unsafe { ptr::read_volatile((UID_ADDRESS + i) as *const u8) };
+    }
+    uid
+}
+
+/// Stable 32-bit hash of the unique ID (FNV-1a)
+pub fn hash() -> u32 {
+    read().iter().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Default 40-bit radio address derived from the unique ID
+///
+/// The upper byte is `0xE7`, as in the official default address, while the lower four bytes are
+/// derived from the unique ID.
+pub fn default_radio_address() -> u64 {
+    (RADIO_ADDRESS_UPPER << 32) | hash() as u64
+}
+
+/// Device name derived from the unique ID, e.g. `CF-1A2B3C4D`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceName([u8; NAME_LEN]);
+
+impl DeviceName {
+    /// The name as a string slice
+    pub fn as_str(&self) -> &str {
+        // Unwrap safety: The name only consists of ASCII characters
+        str::from_utf8(&self.0).unwrap()
+    }
+}
+
+/// Device name derived from the unique ID
+pub fn device_name() -> DeviceName {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let mut name = [0u8; NAME_LEN];
+    name[..NAME_PREFIX.len()].copy_from_slice(NAME_PREFIX);
+    let hash = hash();
+    for (i, c) in name[NAME_PREFIX.len()..].iter_mut().enumerate() {
+        *c = HEX[((hash >> (28 - 4 * i)) & 0xF) as usize];
+    }
+    DeviceName(name)
+}
@@ -0,0 +1,32 @@
+//! Storage of wakers shared between async tasks and interrupt handlers
+use core::cell::RefCell;
+use core::task::Waker;
+use cortex_m::interrupt::{self, Mutex};
+
+/// A single [`Waker`] which can be registered from a task and woken from an interrupt
+pub(crate) struct WakerSlot(Mutex<RefCell<Option<Waker>>>);
+
+impl WakerSlot {
+    /// Create an empty slot
+    pub(crate) const fn new() -> Self {
+        WakerSlot(Mutex::new(RefCell::new(None)))
+    }
+
+    /// Register the waker to wake on the next call to [`WakerSlot::wake`]
+    pub(crate) fn register(&self, waker: &Waker) {
+        interrupt::free(|cs| {
+            let mut slot = self.0.borrow(cs).borrow_mut();
+            match *slot {
+                Some(ref old) if old.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        });
+    }
+
+    /// Wake the registered waker, if any
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = interrupt::free(|cs| self.0.borrow(cs).borrow_mut().take()) {
+            waker.wake();
+        }
+    }
+}
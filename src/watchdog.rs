@@ -0,0 +1,194 @@
+//! Independent watchdog with per-task check-ins
+//!
+//! Subsystems which must never stall (control loop, syslink receive, estimator, ...) register in
+//! [`CheckIns`] and must check in every watchdog period. The [`Watchdog`] is only fed once all
+//! registered tasks have checked in, if any task stalls the `IWDG` resets the MCU. After a reset
+//! all pins are inputs so the motors are turned off.
+//!
+//! # Usage
+//! Place [`CheckIns`] in a `static` and [register](CheckIns::register) each task. Then start the
+//! [`Watchdog`] and call [`Watchdog::feed`] periodically, e.g. from a timer, while each task calls
+//! [`CheckIns::check_in`] with its [`TaskId`].
+use crate::hal::pac::IWDG;
+use crate::hal::prelude::*;
+use crate::hal::watchdog::IndependentWatchdog;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Maximum number of tasks which can be registered
+pub const MAX_TASKS: usize = 32;
+
+/// Identifier of a registered task
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TaskId(u8);
+
+impl TaskId {
+    /// Index of the task, tasks are numbered in order of registration
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Registry of tasks which must check in to keep the watchdog from resetting the MCU
+///
+/// This is designed to be placed in a `static` so that tasks can check in from any context.
+pub struct CheckIns {
+    registered: AtomicU32,
+    checked_in: AtomicU32,
+}
+
+impl CheckIns {
+    /// Create an empty registry
+    pub const fn new() -> Self {
+        CheckIns {
+            registered: AtomicU32::new(0),
+            checked_in: AtomicU32::new(0),
+        }
+    }
+
+    /// Register a new task, returns `None` if [`MAX_TASKS`] are already registered
+    pub fn register(&self) -> Option<TaskId> {
+        let mut current = self.registered.load(Ordering::Relaxed);
+        loop {
+            if current == u32::MAX {
+                return None;
+            }
+            let bit = (!current).trailing_zeros();
+            match self.registered.compare_exchange_weak(
+                current,
+                current | (1 << bit),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(TaskId(bit as u8)),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Signal that a task is alive
+    pub fn check_in(&self, task: TaskId) {
+        self.checked_in.fetch_or(1 << task.0, Ordering::Relaxed);
+    }
+
+    /// Bitmask of registered tasks which have not yet checked in this period
+    ///
+    /// Bit `n` corresponds to the task with [`TaskId::index`] `n`.
+    pub fn missing(&self) -> u32 {
+        self.registered.load(Ordering::Relaxed) & !self.checked_in.load(Ordering::Relaxed)
+    }
+
+    /// If all tasks have checked in, clear the check-ins for the next period and return `true`
+    fn complete_period(&self) -> bool {
+        let registered = self.registered.load(Ordering::Relaxed);
+        let mut current = self.checked_in.load(Ordering::Relaxed);
+        loop {
+            if current & registered != registered {
+                return false;
+            }
+            // Retry if a task checked in meanwhile, so that the check and the clear see one value
+            match self.checked_in.compare_exchange_weak(
+                current,
+                current & !registered,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl Default for CheckIns {
+    fn default() -> Self {
+        CheckIns::new()
+    }
+}
+
+/// Independent watchdog which is only fed when all registered tasks are alive
+pub struct Watchdog {
+    iwdg: IndependentWatchdog,
+    tasks: &'static CheckIns,
+}
+
+impl Watchdog {
+    /// Start the independent watchdog with a timeout in milliseconds
+    ///
+    /// Once started the watchdog can not be stopped.
+    pub fn start(iwdg: IWDG, tasks: &'static CheckIns, timeout_ms: u32) -> Self {
+        let mut iwdg = IndependentWatchdog::new(iwdg);
+        iwdg.start(timeout_ms.ms());
+        Watchdog { iwdg, tasks }
+    }
+
+    /// Feed the watchdog if all registered tasks have checked in since the last feed
+    ///
+    /// Returns `true` if the watchdog was fed. This must be called more often than the timeout,
+    /// but should not be called so often that tasks do not have time to check in.
+    pub fn feed(&mut self) -> bool {
+        if self.tasks.complete_period() {
+            self.iwdg.feed();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The tasks registered with this watchdog
+    pub fn tasks(&self) -> &'static CheckIns {
+        self.tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_once_all_tasks_checked_in() {
+        let tasks = CheckIns::new();
+        let a = tasks.register().unwrap();
+        let b = tasks.register().unwrap();
+        assert_eq!((a.index(), b.index()), (0, 1));
+        assert_eq!(tasks.missing(), 0b11);
+        tasks.check_in(a);
+        assert_eq!(tasks.missing(), 0b10);
+        assert!(!tasks.complete_period());
+        tasks.check_in(b);
+        assert!(tasks.complete_period());
+        // A new period starts with all tasks missing
+        assert_eq!(tasks.missing(), 0b11);
+        assert!(!tasks.complete_period());
+    }
+
+    #[test]
+    fn limited_to_max_tasks() {
+        let tasks = CheckIns::default();
+        for i in 0..MAX_TASKS {
+            assert_eq!(tasks.register().map(TaskId::index), Some(i));
+        }
+        assert_eq!(tasks.register(), None);
+        for i in 0..MAX_TASKS {
+            tasks.check_in(TaskId(i as u8));
+        }
+        assert!(tasks.complete_period());
+    }
+
+    #[test]
+    fn clears_only_registered_tasks() {
+        let tasks = CheckIns::new();
+        let a = tasks.register().unwrap();
+        // Check-in of a task which is not registered yet
+        tasks.check_in(TaskId(5));
+        tasks.check_in(a);
+        assert!(tasks.complete_period());
+        assert_eq!(tasks.checked_in.load(Ordering::Relaxed), 1 << 5);
+    }
+
+    #[test]
+    fn no_tasks_always_completes() {
+        let tasks = CheckIns::new();
+        assert!(tasks.complete_period());
+        assert_eq!(tasks.missing(), 0);
+    }
+}